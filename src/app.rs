@@ -1,4 +1,6 @@
 use std::{
+    collections::VecDeque,
+    path::PathBuf,
     sync::{
         mpsc::{Receiver, Sender},
         OnceLock,
@@ -7,8 +9,25 @@ use std::{
 };
 
 use crate::{
-    mp_telemetry_data::STelemetry,
-    tcp_server::{FromTM, LAST_CONTEXT}, ALT_HELD_AT_STARTUP,
+    autostart::{is_registered, set_run_at_login},
+    bridge_state::SharedState,
+    camera::{CameraMode, MIN_CHASE_DISTANCE},
+    config::{config_dir, get_config, update_config},
+    diagnostics::{ui_diagnostics, ErrorHistory, RateMeter},
+    log_view::LogView,
+    maniaplanet_telemetry::TelemetryStatus,
+    park_rules::ParkReason,
+    process_watcher::ProcessStatus,
+    race_events::RaceEvent,
+    radar::Radar,
+    source_manager::SourceKind,
+    spectator::{SpectatorListenAt, SpectatorMode},
+    tcp_server::FromTM,
+    telemetry_export::{export_file, is_exporting, start_export, stop_export, ExportFormat, ExportOptions},
+    telemetry_frame::TelemetryFrame,
+    telemetry_view::TelemetryView,
+    tray::{LinkState, Tray, TrayStatus},
+    hide_window,
 };
 use eframe::App;
 use egui::vec2;
@@ -17,6 +36,11 @@ use serde::{Deserialize, Serialize};
 
 pub const MUMBLE_SCALE_INV: f32 = 32.0;
 pub const MUMBLE_SCALE: f32 = 1.0 / 32.0;
+const MAX_RACE_EVENTS: usize = 12;
+/// Positions older than this mean the link isn't live.
+const LIVE_TIMEOUT: Duration = Duration::from_secs(3);
+/// How long an error keeps the tray icon red.
+const ERROR_SHOWN_FOR: Duration = Duration::from_secs(30);
 
 #[derive(Debug, Clone)]
 pub enum ToGUI {
@@ -24,9 +48,14 @@ pub enum ToGUI {
     IsConnected(bool),
     MumbleError(String),
     ListeningOn(String, u16),
+    /// The plugin port was taken; `actual` is the fallback used, if any.
+    PortInUse { wanted: u16, actual: Option<u16> },
     ProtocolError(String),
     FromTM(FromTM),
-    Telemetry(STelemetry),
+    TelemetryStatus(TelemetryStatus),
+    SourceChanged(Option<SourceKind>),
+    ParkStatus(Option<ParkReason>),
+    Processes(ProcessStatus),
     // HideMainWindow()
 }
 
@@ -38,8 +67,11 @@ impl From<FromTM> for ToGUI {
 
 pub enum FromGuiToServer {
     TryConnectMumble(),
-    UseManiaPlanetTelemetry(),
-    UseSocketServer(),
+    /// Sent by the process watcher when Mumble starts; ignored if already linked.
+    AutoConnectMumble(),
+    /// Drop the current link (if any) and link again.
+    ReconnectMumble(),
+    UseSource(SourceKind),
     #[allow(unused)]
     Shutdown(),
 }
@@ -56,7 +88,7 @@ pub struct MumbleBridgeApp<'a> {
     server_login: String,
     server_team: String,
     #[serde(skip)]
-    telemetry: Option<STelemetry>,
+    telemetry: Option<TelemetryFrame>,
     #[serde(skip)]
     e_state: MumbleBridgeEphemeralState,
     #[serde(skip)]
@@ -64,7 +96,9 @@ pub struct MumbleBridgeApp<'a> {
     #[serde(skip)]
     tx_gui: OnceLock<Sender<FromGuiToServer>>,
     #[serde(skip)]
-    shutdown_tx: OnceLock<Sender<()>>,
+    tray: Option<Tray>,
+    #[serde(skip)]
+    state: SharedState,
 }
 
 impl App for MumbleBridgeApp<'_> {
@@ -73,6 +107,7 @@ impl App for MumbleBridgeApp<'_> {
         self.render_main_top(ctx, frame);
         self.render_main_body(ctx, frame);
         self.render_main_footer(ctx, frame);
+        self.refresh_tray();
         ctx.request_repaint_after(Duration::from_millis(50));
 
         // frame.set_window_size(Vec2::new(400.0, 240.0));
@@ -93,7 +128,7 @@ impl Default for MumbleBridgeApp<'_> {
             in_server: false,
             client_connected: false,
             has_chosen_method: false,
-            offer_manual_choice: *ALT_HELD_AT_STARTUP.lock().unwrap(),
+            offer_manual_choice: false,
             player_name: String::new(),
             player_login: String::new(),
             server_login: String::new(),
@@ -102,7 +137,8 @@ impl Default for MumbleBridgeApp<'_> {
             e_state: Default::default(),
             rx_gui: OnceLock::new(),
             tx_gui: OnceLock::new(),
-            shutdown_tx: OnceLock::new(),
+            tray: None,
+            state: SharedState::default(),
         }
     }
 }
@@ -111,17 +147,88 @@ impl MumbleBridgeApp<'_> {
     pub fn new<'a>(
         rx_gui: &'a mut Receiver<ToGUI>,
         tx_gui: Sender<FromGuiToServer>,
-        shutdown_tx: Sender<()>,
+        state: SharedState,
     ) -> MumbleBridgeApp<'a> {
-        let app = MumbleBridgeApp::default();
+        let mut app = MumbleBridgeApp {
+            offer_manual_choice: state.alt_held_at_startup(),
+            state,
+            ..Default::default()
+        };
+        app.e_state.race_event_rx = Some(app.state.subscribe_race_events());
         app.rx_gui.set(rx_gui).expect("Failed to set rx_gui");
         app.tx_gui.set(tx_gui).expect("Failed to set tx_gui");
-        app.shutdown_tx
-            .set(shutdown_tx)
-            .expect("Failed to set shutdown_tx");
         app
     }
 
+    pub fn with_tray(mut self, tray: Tray) -> Self {
+        self.tray = Some(tray);
+        self
+    }
+
+    fn refresh_tray(&mut self) {
+        let Some(mut tray) = self.tray.take() else {
+            return;
+        };
+        // from the shared state, since the GUI's own copies only update while it's shown
+        tray.refresh(&self.state, || {
+            let link = self.state.link();
+            let player = if link.player_name.is_empty() {
+                self.state.telemetry_ctx().player_name.clone()
+            } else {
+                link.player_name
+            };
+            TrayStatus {
+                link: self.link_state(),
+                mumble_linked: link.mumble_connected,
+                tm: self.tm_status_text(),
+                context: link.context,
+                player,
+                server: link.server_login,
+                team: link.server_team,
+                since_update: self.since_last_update(),
+            }
+        });
+        self.tray = Some(tray);
+    }
+
+    fn link_state(&self) -> LinkState {
+        let recent_error = self
+            .e_state
+            .errors
+            .current()
+            .is_some_and(|e| e.at.elapsed() < ERROR_SHOWN_FOR);
+        let parked = matches!(self.e_state.park, Some(Some(_)));
+        if recent_error {
+            LinkState::Error
+        } else if !self.connected {
+            LinkState::Disconnected
+        } else if self.e_state.source.is_some() && !parked && self.since_last_update() < LIVE_TIMEOUT {
+            LinkState::Linked
+        } else {
+            LinkState::MumbleOnly
+        }
+    }
+
+    /// One-line summary of the game side, for the tray.
+    fn tm_status_text(&self) -> String {
+        let telemetry = match self.e_state.telemetry_status {
+            Some(TelemetryStatus::Running) => "telemetry live",
+            Some(TelemetryStatus::Stalled) => "telemetry stalled",
+            Some(TelemetryStatus::WaitingForGame) | None => "waiting for game",
+        };
+        let plugin = if self.client_connected {
+            "plugin connected"
+        } else {
+            "waiting for plugin"
+        };
+        match self.e_state.source {
+            None => "no source".to_string(),
+            Some(SourceKind::Plugin) => plugin.to_string(),
+            Some(SourceKind::Telemetry) => telemetry.to_string(),
+            Some(SourceKind::Hybrid) => format!("{}, {}", plugin, telemetry),
+        }
+    }
+
     fn _update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
         let mut reset_error_msg = false;
         if let Some(rx) = self.rx_gui.get_mut() {
@@ -130,35 +237,61 @@ impl MumbleBridgeApp<'_> {
                     // ToGUI::TaskBarIconMsg(msg) => {
                     //     self.e_state.last_task_bar_msg = msg;
                     // },
-                    ToGUI::Telemetry(telemetry) => {
-                        self.telemetry.replace(telemetry);
-                        // log::info!("Got telemetry: {:?}", telemetry.object);
+                    ToGUI::TelemetryStatus(status) => {
+                        if status == TelemetryStatus::Stalled {
+                            self.e_state.errors.warning("Telemetry stopped updating");
+                        }
+                        self.e_state.telemetry_status = Some(status);
+                    }
+                    ToGUI::SourceChanged(Some(kind)) => {
+                        self.has_chosen_method = true;
+                        self.e_state.source = Some(kind);
+                    }
+                    ToGUI::Processes(status) => {
+                        self.e_state.processes = status;
+                    }
+                    ToGUI::ParkStatus(reason) => {
+                        self.e_state.park = Some(reason);
+                    }
+                    ToGUI::SourceChanged(None) => {
+                        self.e_state.source = None;
+                        self.e_state.port_warning = None;
+                        self.e_state.park = None;
+                        self.e_state.listening = None;
+                        self.e_state.telemetry_status = None;
+                        self.client_connected = false;
+                        self.telemetry = None;
                     }
                     ToGUI::IsConnected(is_connected) => {
                         self.connected = is_connected;
                     }
                     ToGUI::MumbleError(e) => {
-                        self.e_state.last_error_msg = e;
-                        self.e_state.last_error_msg_time = Instant::now();
+                        self.e_state.errors.error(e);
                     }
                     ToGUI::ListeningOn(ip, port) => {
                         self.e_state.listening = Some((ip, port));
                     }
+                    ToGUI::PortInUse { wanted, actual } => {
+                        let warning = match actual {
+                            Some(port) => format!(
+                                "Port {} is used by another program. Listening on {} instead: set the plugin's port to {}.",
+                                wanted, port, port
+                            ),
+                            None => format!(
+                                "Port {} and its fallbacks are used by other programs. Pick another port under Plugin server.",
+                                wanted
+                            ),
+                        };
+                        self.e_state.errors.warning(warning.clone());
+                        self.e_state.port_warning = Some(warning);
+                    }
                     ToGUI::ProtocolError(e) => {
-                        self.e_state.last_error_msg = e;
-                        self.e_state.last_error_msg_time = Instant::now();
+                        self.e_state.errors.error(e);
                     }
                     // ToGUI::HideMainWindow() => {
                     //     hide_window = true;
                     // },
                     ToGUI::FromTM(from_tm) => match from_tm {
-                        FromTM::Positions { p, c } => {
-                            self.e_state.last_player_pos =
-                                vec_flip_z(vecm(p.pos, MUMBLE_SCALE_INV));
-                            self.e_state.last_camera_pos =
-                                vec_flip_z(vecm(c.pos, MUMBLE_SCALE_INV));
-                            self.e_state.last_update = Instant::now();
-                        }
                         FromTM::LeftServer() => {
                             self.in_server = false;
                             self.server_login = String::new();
@@ -190,13 +323,48 @@ impl MumbleBridgeApp<'_> {
         if reset_error_msg {
             self.reset_err_msg();
         }
+        // high-frequency data is polled rather than queued, so only the newest value is kept
+        if let Some(t) = self.state.telemetry_if_newer(&mut self.e_state.telemetry_seen) {
+            if self.e_state.source.is_some() {
+                self.telemetry = Some(t);
+            }
+        }
+        self.e_state.rates.sample(Instant::now(), self.state.stats());
+        if let Some(rx) = &self.e_state.race_event_rx {
+            for event in rx.try_iter() {
+                if self.e_state.race_events.len() >= MAX_RACE_EVENTS {
+                    self.e_state.race_events.pop_front();
+                }
+                self.e_state.race_events.push_back(event);
+            }
+        }
+        if let Some(pos) = self.state.positions_if_newer(&mut self.e_state.positions_seen) {
+            self.e_state.last_player_pos = vec_flip_z(vecm(pos.p.pos, MUMBLE_SCALE_INV));
+            self.e_state.last_camera_pos = vec_flip_z(vecm(pos.c.pos, MUMBLE_SCALE_INV));
+            self.e_state.last_update = pos.at;
+            self.e_state.radar.push(&pos);
+        }
         if ctx.input(|i| i.viewport().close_requested()) {
             log::info!("Close requested");
+            // an exit from the tray or a signal closes the window too; let that one through
+            if get_config().startup.close_to_tray && !self.state.is_shutting_down() {
+                ctx.send_viewport_cmd(egui::ViewportCommand::CancelClose);
+                hide_window(ctx, &self.state);
+            }
         }
     }
 
+    fn use_source(&mut self, kind: SourceKind) {
+        self.tx_gui
+            .get()
+            .expect("tx_gui not set")
+            .send(FromGuiToServer::UseSource(kind))
+            .expect("to send to server");
+        self.has_chosen_method = true;
+    }
+
     fn reset_err_msg(&mut self) {
-        self.e_state.last_error_msg = String::new();
+        self.e_state.errors.dismiss();
     }
 
     fn render_main_top(&self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
@@ -217,13 +385,21 @@ impl MumbleBridgeApp<'_> {
                 //     // self.hide_main_window(ctx, frame);
                 // }
                 if self.connected && self.has_chosen_method {
+                    self.ui_source_selector(ui);
                     self.ui_listening_on(ui);
+                    self.ui_telemetry_status(ui);
+                    self.ui_park_status(ui);
                     ui.horizontal(|ui| {
                         self.ui_mumble_status(ui);
                         self.ui_tm_game_status(ui);
                     });
                     self.ui_last_positions(ui);
                     self.ui_curr_details(ui);
+                    self.ui_smoothing_settings(ui);
+                    self.ui_park_settings(ui);
+                    self.ui_auto_detect_settings(ui);
+                    self.ui_startup_settings(ui);
+                    self.ui_plugin_server_settings(ui);
                 } else if !self.connected {
                     if ui.button("Connect to Mumble").clicked() {
                         self.tx_gui
@@ -231,47 +407,38 @@ impl MumbleBridgeApp<'_> {
                             .expect("tx_gui not set")
                             .send(FromGuiToServer::TryConnectMumble())
                             .expect("to send to server");
-                        self.e_state.last_error_msg = String::new();
+                        self.e_state.errors.dismiss();
                     }
                 } else if !self.has_chosen_method && !self.offer_manual_choice {
-                    self.tx_gui
-                        .get()
-                        .expect("tx_gui not set")
-                        .send(FromGuiToServer::UseSocketServer())
-                        .expect("to send to server");
-                    self.has_chosen_method = true;
+                    let source = get_config().default_source.unwrap_or(SourceKind::Plugin);
+                    self.use_source(source);
                 } else if !self.has_chosen_method && self.offer_manual_choice {
                     if ui.button("Use the Plugin (Recommended)").clicked() {
-                        self.tx_gui
-                            .get()
-                            .expect("tx_gui not set")
-                            .send(FromGuiToServer::UseSocketServer())
-                            .expect("to send to server");
-                        self.e_state.last_error_msg = String::new();
-                        self.has_chosen_method = true;
+                        self.use_source(SourceKind::Plugin);
+                        self.e_state.errors.dismiss();
                         ctx.send_viewport_cmd(egui::ViewportCommand::MinInnerSize(vec2(400.0, 240.0)));
                         ctx.send_viewport_cmd(egui::ViewportCommand::MaxInnerSize(vec2(400.0, 240.0)));
                         ctx.send_viewport_cmd(egui::ViewportCommand::InnerSize(vec2(400.0, 240.0)));
                     }
                     if ui.button("Use TM Telemetry").clicked() {
-                        self.tx_gui
-                            .get()
-                            .expect("tx_gui not set")
-                            .send(FromGuiToServer::UseManiaPlanetTelemetry())
-                            .expect("to send to server");
-                        self.e_state.last_error_msg = String::new();
-                        self.has_chosen_method = true;
+                        self.use_source(SourceKind::Telemetry);
+                        self.e_state.errors.dismiss();
                     }
                 } else {
                     ui.label("UNKNOWN STATE");
                 }
 
-                if let Some(t) = self.telemetry.as_ref() {
-                    self.render_telemetry(ui, t);
+                if let Some(t) = self.telemetry.clone() {
+                    self.ui_camera_settings(ui);
+                    self.ui_race_events(ui);
+                    self.ui_export_settings(ui);
+                    self.render_telemetry(ui, &t);
                 }
 
                 self.ui_dbg_task_bar_msg(ui);
                 self.ui_opt_last_error_msg(ui);
+                ui_diagnostics(ui, &self.state.stats(), &self.e_state.rates.rates(), &self.e_state.errors);
+                self.e_state.log_view.ui(ui);
             });
         });
     }
@@ -287,6 +454,8 @@ impl MumbleBridgeApp<'_> {
                         ui.separator();
                         self.ui_tm_game_status_small(ui);
                         ui.separator();
+                        self.ui_processes_small(ui);
+                        ui.separator();
                     });
                 });
 
@@ -315,10 +484,10 @@ impl MumbleBridgeApp<'_> {
                 self.server_login, self.server_team
             ));
         });
-        ui.label(format!("Mumble Ctx: {}", &LAST_CONTEXT.lock().unwrap()));
+        ui.label(format!("Mumble Ctx: {}", self.state.context()));
     }
 
-    fn ui_last_positions(&self, ui: &mut egui::Ui) {
+    fn ui_last_positions(&mut self, ui: &mut egui::Ui) {
         let p1 = fmt_vec3(self.e_state.last_player_pos);
         let p2 = fmt_vec3(self.e_state.last_camera_pos);
         ui.label("Last positions:");
@@ -344,18 +513,69 @@ impl MumbleBridgeApp<'_> {
                     });
                 });
         });
+        egui::CollapsingHeader::new("Radar")
+            .default_open(true)
+            .show(ui, |ui| self.e_state.radar.ui(ui));
+    }
+
+    fn ui_source_selector(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Source:");
+            for kind in SourceKind::ALL {
+                if ui
+                    .selectable_label(self.e_state.source == Some(kind), kind.to_string())
+                    .clicked()
+                    && self.e_state.source != Some(kind)
+                {
+                    self.use_source(kind);
+                }
+            }
+        });
     }
 
     fn ui_listening_on(&self, ui: &mut egui::Ui) {
         if let Some((ip, port)) = &self.e_state.listening {
             ui.label(format!("Listening on {}:{}", ip, port));
         }
+        if let Some(warning) = &self.e_state.port_warning {
+            ui.colored_label(ui.visuals().warn_fg_color, warning);
+        }
+    }
+
+    fn ui_telemetry_status(&self, ui: &mut egui::Ui) {
+        match self.e_state.telemetry_status {
+            Some(TelemetryStatus::WaitingForGame) => {
+                ui.horizontal(|ui| {
+                    ui.spinner();
+                    ui.label("Waiting for Trackmania telemetry...");
+                });
+            }
+            Some(TelemetryStatus::Stalled) => {
+                ui.label("Telemetry stalled (game paused or minimized?)");
+            }
+            Some(TelemetryStatus::Running) | None => {}
+        }
+    }
+
+    fn ui_park_status(&self, ui: &mut egui::Ui) {
+        match self.e_state.park {
+            Some(Some(reason)) => ui.label(format!("Positional audio: parked ({})", reason)),
+            Some(None) => ui.label("Positional audio: live"),
+            None => return,
+        };
     }
 
     fn ui_mumble_status_small(&self, ui: &mut egui::Ui) {
         ui.label(if self.connected { "M: ✅" } else { "M: ❌" });
     }
 
+    fn ui_processes_small(&self, ui: &mut egui::Ui) {
+        let p = self.e_state.processes;
+        let icon = |running| if running { "✅" } else { "❌" };
+        ui.label(format!("Game: {}  Mumble app: {}", icon(p.game_running), icon(p.mumble_running)))
+            .on_hover_text("Whether Trackmania/ManiaPlanet and Mumble are running");
+    }
+
     fn ui_mumble_status(&self, ui: &mut egui::Ui) {
         ui.label(if self.connected {
             "Mumble: ✅"
@@ -411,8 +631,8 @@ impl MumbleBridgeApp<'_> {
     // }
 
     fn ui_opt_last_error_msg(&self, ui: &mut egui::Ui) {
-        if !self.e_state.last_error_msg.is_empty() {
-            ui.label(&self.e_state.last_error_msg);
+        if let Some(e) = self.e_state.errors.current() {
+            ui.label(&e.msg);
         }
     }
 
@@ -421,32 +641,206 @@ impl MumbleBridgeApp<'_> {
         _ui.label(&self.e_state.last_task_bar_msg);
     }
 
-    fn render_telemetry(&self, ui: &mut egui::Ui, telemetry: &STelemetry) {
-        // egui::CentralPanel::default().show(ui, |ui| {
-        ui.label("Telemetry");
-        // header
-        ui.label(format!("Header: {:#?}", telemetry.header));
-        ui.label(format!("UpdateNb: {:#?}", telemetry.update_number));
-        ui.label(format!("Game: {:#?}", &telemetry.game));
-        ui.label(format!("Race: {:#?}", &telemetry.race));
-        ui.label(format!("Object: {:#?}", &telemetry.object));
-        ui.label(format!("Vehicle: {:#?}", &telemetry.vehicle));
-        ui.label(format!("Device: {:#?}", &telemetry.device));
-        ui.label(format!("Player: {:#?}", &telemetry.player));
-        // });
+    fn ui_camera_settings(&self, ui: &mut egui::Ui) {
+        egui::CollapsingHeader::new("Camera").show(ui, |ui| {
+            let mut cam = get_config().camera;
+            ui.horizontal(|ui| {
+                ui.radio_value(&mut cam.mode, CameraMode::ChaseCam, "Chase cam");
+                ui.radio_value(&mut cam.mode, CameraMode::FirstPerson, "First person");
+            });
+            match cam.mode {
+                CameraMode::ChaseCam => {
+                    ui.add(egui::Slider::new(&mut cam.chase_distance, MIN_CHASE_DISTANCE..=30.0).text("Distance behind"));
+                    ui.add(egui::Slider::new(&mut cam.chase_height, 0.0..=15.0).text("Height above"));
+                    ui.add(egui::Slider::new(&mut cam.velocity_blend, 0.0..=1.0).text("Follow velocity"));
+                }
+                CameraMode::FirstPerson => {
+                    ui.add(egui::Slider::new(&mut cam.first_person_height, 0.0..=3.0).text("Eye height"));
+                }
+            }
+            if cam != get_config().camera {
+                update_config(|c| c.camera = cam);
+            }
+        });
+    }
+
+    fn ui_smoothing_settings(&self, ui: &mut egui::Ui) {
+        egui::CollapsingHeader::new("Smoothing").show(ui, |ui| {
+            let mut sm = get_config().smoothing;
+            ui.checkbox(&mut sm.enabled, "Interpolate / extrapolate positions");
+            ui.add_enabled_ui(sm.enabled, |ui| {
+                ui.add(egui::Slider::new(&mut sm.update_rate_hz, 10.0..=240.0).text("Mumble updates/s"));
+                ui.add(egui::Slider::new(&mut sm.interpolation_delay_ms, 0..=100).text("Interp. delay (ms)"));
+                ui.add(egui::Slider::new(&mut sm.max_extrapolation_ms, 0..=1000).text("Max extrap. (ms)"));
+            });
+            if sm != get_config().smoothing {
+                update_config(|c| c.smoothing = sm);
+            }
+        });
+    }
+
+    fn ui_auto_detect_settings(&self, ui: &mut egui::Ui) {
+        egui::CollapsingHeader::new("Auto-detect").show(ui, |ui| {
+            let mut auto = get_config().auto_detect;
+            ui.checkbox(&mut auto.auto_connect_mumble, "Connect when Mumble starts");
+            ui.checkbox(&mut auto.auto_select_source, "Use telemetry if the plugin doesn't connect");
+            ui.add_enabled_ui(auto.auto_select_source, |ui| {
+                ui.add(egui::Slider::new(&mut auto.plugin_timeout_secs, 5..=120).text("Plugin timeout (s)"));
+            });
+            if auto != get_config().auto_detect {
+                update_config(|c| c.auto_detect = auto);
+            }
+        });
+    }
+
+    fn ui_startup_settings(&mut self, ui: &mut egui::Ui) {
+        egui::CollapsingHeader::new("Tray & startup").show(ui, |ui| {
+            let mut startup = get_config().startup;
+            ui.checkbox(&mut startup.start_hidden, "Start hidden in the tray");
+            ui.checkbox(&mut startup.close_to_tray, "Closing the window hides it to the tray");
+            ui.checkbox(&mut startup.run_at_login, "Run at login");
+            if startup.run_at_login != get_config().startup.run_at_login {
+                if let Err(e) = set_run_at_login(startup.run_at_login) {
+                    log::error!("Failed to change autostart: {}", e);
+                    self.e_state.errors.error(format!("Failed to change autostart: {}", e));
+                    startup.run_at_login = is_registered();
+                }
+            }
+            if startup != get_config().startup {
+                update_config(|c| c.startup = startup);
+            }
+        });
+    }
+
+    fn ui_plugin_server_settings(&self, ui: &mut egui::Ui) {
+        egui::CollapsingHeader::new("Plugin server").show(ui, |ui| {
+            let mut server = get_config().plugin_server;
+            ui.horizontal(|ui| {
+                ui.label("Port:");
+                ui.add(egui::DragValue::new(&mut server.port).range(1024..=65535));
+                ui.label("Fallbacks:");
+                ui.add(egui::DragValue::new(&mut server.fallback_ports).range(0..=100));
+            });
+            ui.label("Used when the plugin source (re)starts. The plugin must use the same port.");
+            if server != get_config().plugin_server {
+                update_config(|c| c.plugin_server = server);
+            }
+        });
+    }
+
+    fn ui_park_settings(&self, ui: &mut egui::Ui) {
+        egui::CollapsingHeader::new("Parking & spectating").show(ui, |ui| {
+            ui.label("Move out of hearing range when:");
+            let mut park = get_config().park;
+            ui.checkbox(&mut park.in_menus, "In menus");
+            ui.checkbox(&mut park.when_paused, "Paused");
+            ui.checkbox(&mut park.after_finish, "Finished the race");
+            if park != get_config().park {
+                update_config(|c| c.park = park);
+            }
+
+            ui.label("When spectating or watching a replay:");
+            let mut spec = get_config().spectator;
+            ui.horizontal(|ui| {
+                for mode in SpectatorMode::ALL {
+                    ui.radio_value(&mut spec.mode, mode, mode.label());
+                }
+            });
+            ui.add_enabled_ui(spec.mode != SpectatorMode::Park, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("Listen at:");
+                    ui.radio_value(&mut spec.listen_at, SpectatorListenAt::Car, "Car");
+                    ui.radio_value(&mut spec.listen_at, SpectatorListenAt::Camera, "Camera");
+                });
+            });
+            if spec != get_config().spectator {
+                update_config(|c| c.spectator = spec);
+            }
+        });
+    }
+
+    fn ui_export_settings(&mut self, ui: &mut egui::Ui) {
+        egui::CollapsingHeader::new("Export").show(ui, |ui| {
+            let exporting = is_exporting(&self.state);
+            ui.add_enabled_ui(!exporting, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("File:");
+                    ui.text_edit_singleline(&mut self.e_state.export_path);
+                });
+                ui.horizontal(|ui| {
+                    let fmt = &mut self.e_state.export_format;
+                    ui.radio_value(fmt, ExportFormat::Csv, "CSV");
+                    ui.radio_value(fmt, ExportFormat::JsonLines, "JSON lines");
+                    ui.checkbox(&mut self.e_state.export_split, "One file per run");
+                });
+            });
+            ui.horizontal(|ui| {
+                if exporting {
+                    if ui.button("Stop export").clicked() {
+                        stop_export(&self.state);
+                    }
+                } else if ui.button("Start export").clicked() {
+                    let format = self.e_state.export_format;
+                    let path = PathBuf::from(self.e_state.export_path.trim()).with_extension(format.extension());
+                    self.e_state.export_path = path.display().to_string();
+                    start_export(&self.state, ExportOptions {
+                        path,
+                        format,
+                        split_per_run: self.e_state.export_split,
+                    });
+                }
+                if let Some(file) = export_file(&self.state) {
+                    ui.label(format!("Writing {}", file.display()));
+                }
+            });
+        });
+    }
+
+    fn ui_race_events(&self, ui: &mut egui::Ui) {
+        ui.label("Race events:");
+        ui.indent("race_events", |ui| {
+            if self.e_state.race_events.is_empty() {
+                ui.label("None yet");
+            }
+            for event in self.e_state.race_events.iter().rev() {
+                ui.label(event.to_string());
+            }
+        });
+    }
+
+    fn render_telemetry(&mut self, ui: &mut egui::Ui, telemetry: &TelemetryFrame) {
+        self.e_state.telemetry_view.ui(ui, telemetry);
     }
 }
 
 struct MumbleBridgeEphemeralState {
     last_update: Instant,
     last_ping: Instant,
-    last_error_msg: String,
-    last_error_msg_time: Instant,
+    /// Errors and warnings, with the newest error shown until dismissed.
+    errors: ErrorHistory,
+    rates: RateMeter,
     #[allow(unused)]
     last_task_bar_msg: String,
     last_player_pos: [f32; 3],
     last_camera_pos: [f32; 3],
     listening: Option<(String, u16)>,
+    port_warning: Option<String>,
+    /// Versions of the latest-value cells last read.
+    telemetry_seen: u64,
+    positions_seen: u64,
+    race_events: VecDeque<RaceEvent>,
+    race_event_rx: Option<Receiver<RaceEvent>>,
+    telemetry_status: Option<TelemetryStatus>,
+    source: Option<SourceKind>,
+    /// `Some` once the active source has reported whether it's parked.
+    park: Option<Option<ParkReason>>,
+    processes: ProcessStatus,
+    telemetry_view: TelemetryView,
+    log_view: LogView,
+    radar: Radar,
+    export_path: String,
+    export_format: ExportFormat,
+    export_split: bool,
 }
 
 impl Default for MumbleBridgeEphemeralState {
@@ -454,12 +848,27 @@ impl Default for MumbleBridgeEphemeralState {
         MumbleBridgeEphemeralState {
             last_update: Instant::now(),
             last_ping: Instant::now(),
-            last_error_msg: String::new(),
-            last_error_msg_time: Instant::now(),
+            errors: ErrorHistory::default(),
+            rates: RateMeter::default(),
             last_task_bar_msg: String::new(),
             last_player_pos: [-1.0, -1.0, -1.0],
             last_camera_pos: [-1.0, -1.0, -1.0],
             listening: None,
+            port_warning: None,
+            telemetry_seen: 0,
+            positions_seen: 0,
+            race_events: VecDeque::new(),
+            race_event_rx: None,
+            telemetry_status: None,
+            source: None,
+            park: None,
+            processes: ProcessStatus::default(),
+            telemetry_view: TelemetryView::default(),
+            log_view: LogView::default(),
+            radar: Radar::default(),
+            export_path: config_dir().join("exports").join("telemetry.csv").display().to_string(),
+            export_format: ExportFormat::Csv,
+            export_split: false,
         }
     }
 }
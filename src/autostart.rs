@@ -0,0 +1,143 @@
+use std::io;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StartupConfig {
+    /// Start with only the tray icon showing.
+    pub start_hidden: bool,
+    /// Hide to the tray when the window is closed; exit from the tray menu.
+    pub close_to_tray: bool,
+    /// Start the bridge when logging in.
+    pub run_at_login: bool,
+}
+
+/// The command that starts this executable, quoted for the autostart entry.
+fn launch_command() -> io::Result<String> {
+    let exe = std::env::current_exe()?;
+    Ok(format!("\"{}\"", exe.display()))
+}
+
+/// Add or remove the autostart entry.
+pub fn set_run_at_login(enabled: bool) -> io::Result<()> {
+    if enabled {
+        register()?;
+        log::info!("Registered to run at login");
+    } else {
+        unregister()?;
+        log::info!("Unregistered from running at login");
+    }
+    Ok(())
+}
+
+#[cfg(windows)]
+mod platform {
+    use std::io;
+
+    use windows::core::{w, PCWSTR};
+    use windows::Win32::Foundation::ERROR_FILE_NOT_FOUND;
+    use windows::Win32::System::Registry::{
+        RegCloseKey, RegDeleteValueW, RegOpenKeyExW, RegQueryValueExW, RegSetValueExW, HKEY,
+        HKEY_CURRENT_USER, KEY_QUERY_VALUE, KEY_SET_VALUE, REG_SAM_FLAGS, REG_SZ,
+    };
+
+    const RUN_KEY: PCWSTR = w!(r"Software\Microsoft\Windows\CurrentVersion\Run");
+    const VALUE_NAME: PCWSTR = w!("TMMumbleLink");
+
+    /// `HKCU\...\Run`, closed on drop.
+    struct RunKey(HKEY);
+
+    impl RunKey {
+        fn open(access: REG_SAM_FLAGS) -> io::Result<RunKey> {
+            let mut key = HKEY::default();
+            unsafe { RegOpenKeyExW(HKEY_CURRENT_USER, RUN_KEY, None, access, &mut key) }
+                .ok()
+                .map_err(io::Error::other)?;
+            Ok(RunKey(key))
+        }
+    }
+
+    impl Drop for RunKey {
+        fn drop(&mut self) {
+            let _ = unsafe { RegCloseKey(self.0) };
+        }
+    }
+
+    pub fn register() -> io::Result<()> {
+        let key = RunKey::open(KEY_SET_VALUE)?;
+        // REG_SZ data is the UTF-16 string including its terminating nul
+        let bytes: Vec<u8> = super::launch_command()?
+            .encode_utf16()
+            .chain(std::iter::once(0))
+            .flat_map(u16::to_le_bytes)
+            .collect();
+        unsafe { RegSetValueExW(key.0, VALUE_NAME, None, REG_SZ, Some(&bytes)) }
+            .ok()
+            .map_err(io::Error::other)
+    }
+
+    pub fn unregister() -> io::Result<()> {
+        let key = RunKey::open(KEY_SET_VALUE)?;
+        match unsafe { RegDeleteValueW(key.0, VALUE_NAME) } {
+            ERROR_FILE_NOT_FOUND => Ok(()),
+            e => e.ok().map_err(io::Error::other),
+        }
+    }
+
+    pub fn is_registered() -> bool {
+        let Ok(key) = RunKey::open(KEY_QUERY_VALUE) else {
+            return false;
+        };
+        unsafe { RegQueryValueExW(key.0, VALUE_NAME, None, None, None, None) }.is_ok()
+    }
+}
+
+#[cfg(not(windows))]
+mod platform {
+    use std::fs;
+    use std::io;
+    use std::path::PathBuf;
+
+    fn desktop_file() -> PathBuf {
+        autostart_dir().join("tm-mumble-link.desktop")
+    }
+
+    /// `$XDG_CONFIG_HOME/autostart`, or `~/.config/autostart`.
+    fn autostart_dir() -> PathBuf {
+        std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|h| PathBuf::from(h).join(".config")))
+            .unwrap_or_else(|| PathBuf::from("."))
+            .join("autostart")
+    }
+
+    pub fn register() -> io::Result<()> {
+        fs::create_dir_all(autostart_dir())?;
+        let entry = format!(
+            "[Desktop Entry]\n\
+             Type=Application\n\
+             Name=TM to Mumble Link\n\
+             Comment=Proximity chat bridge between Trackmania and Mumble\n\
+             Exec={}\n\
+             Terminal=false\n\
+             X-GNOME-Autostart-enabled=true\n",
+            super::launch_command()?
+        );
+        fs::write(desktop_file(), entry)
+    }
+
+    pub fn unregister() -> io::Result<()> {
+        match fs::remove_file(desktop_file()) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            r => r,
+        }
+    }
+
+    pub fn is_registered() -> bool {
+        desktop_file().exists()
+    }
+}
+
+pub use platform::is_registered;
+use platform::{register, unregister};
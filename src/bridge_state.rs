@@ -0,0 +1,409 @@
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock, RwLock};
+use std::time::Instant;
+
+use message_io::network::Endpoint;
+use message_io::node::NodeHandler;
+
+use crate::hybrid::HybridState;
+use crate::latest::Latest;
+use crate::maniaplanet_telemetry::MPData;
+use crate::park_rules::Observed;
+use crate::race_events::RaceEvent;
+use crate::session_log::SessionRecorder;
+use crate::smoothing::PositionSmoother;
+use crate::source_manager::SourceKind;
+use crate::tcp_server::{MPos, SharedMumble};
+use crate::telemetry_export::TelemetryExporter;
+use crate::telemetry_frame::TelemetryFrame;
+
+/// Shared handle to the bridge state. Created once in `main` and cloned into the server, the
+/// active source's threads and the GUI.
+pub type SharedState = Arc<BridgeState>;
+
+/// Who we are to Mumble and where we are, as last sent to the link.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LinkInfo {
+    pub mumble_connected: bool,
+    pub player_name: String,
+    pub player_login: String,
+    pub server_login: String,
+    pub server_team: String,
+    pub context: String,
+    pub identity: String,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct LastPositions {
+    pub p: MPos,
+    pub c: MPos,
+    pub at: Instant,
+}
+
+/// Counters since startup.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BridgeStats {
+    pub plugin_messages: u64,
+    pub plugin_bytes: u64,
+    pub binary_positions: u64,
+    pub json_positions: u64,
+    pub pings: u64,
+    /// Plugin messages we couldn't parse.
+    pub parse_errors: u64,
+    /// Distinct `update_number`s seen in the telemetry.
+    pub telemetry_frames: u64,
+    /// Telemetry snapshots the game was writing to while we copied them.
+    pub torn_reads: u64,
+    pub positions_sent: u64,
+    /// Calls into Mumble's `update`, including the smoothing thread's.
+    pub mumble_updates: u64,
+    pub context_updates: u64,
+}
+
+/// Everything the server, the sources and the GUI share about one running bridge.
+/// Each part has its own lock, and none of them are held across calls into Mumble or the GUI.
+pub struct BridgeState {
+    window_visible: AtomicBool,
+    alt_held_at_startup: AtomicBool,
+    shutdown_requested: AtomicBool,
+    link: RwLock<LinkInfo>,
+    /// High-frequency data goes through latest-value cells rather than the GUI queue.
+    positions: Latest<LastPositions>,
+    telemetry: Latest<TelemetryFrame>,
+    stats: Mutex<BridgeStats>,
+    /// Makes each identity we set unique, so Mumble picks up the change.
+    identity_nonce: AtomicUsize,
+    plugin_handler: Mutex<Option<NodeHandler<()>>>,
+    plugin_endpoint: Mutex<Option<Endpoint>>,
+    /// What the telemetry source last built its context from.
+    telemetry_ctx: Mutex<MPData>,
+    hybrid: Mutex<HybridState>,
+    /// Game state for the park rules.
+    observed: Mutex<Observed>,
+    proximity_enabled: AtomicBool,
+    active_source: Mutex<Option<SourceKind>>,
+    smoother: Mutex<PositionSmoother>,
+    /// The server's Mumble link, for the panic hook.
+    mumble: OnceLock<SharedMumble>,
+    race_event_subscribers: Mutex<Vec<Sender<RaceEvent>>>,
+    /// Plugin traffic recording (`--record-session`).
+    session_recorder: Mutex<Option<SessionRecorder>>,
+    telemetry_exporter: Mutex<Option<TelemetryExporter>>,
+}
+
+impl Default for BridgeState {
+    fn default() -> Self {
+        BridgeState {
+            window_visible: AtomicBool::new(true),
+            alt_held_at_startup: AtomicBool::new(false),
+            shutdown_requested: AtomicBool::new(false),
+            link: RwLock::new(LinkInfo::default()),
+            positions: Latest::default(),
+            telemetry: Latest::default(),
+            stats: Mutex::new(BridgeStats::default()),
+            identity_nonce: AtomicUsize::new(0),
+            plugin_handler: Mutex::new(None),
+            plugin_endpoint: Mutex::new(None),
+            telemetry_ctx: Mutex::new(MPData::default()),
+            hybrid: Mutex::new(HybridState::default()),
+            observed: Mutex::new(Observed::default()),
+            proximity_enabled: AtomicBool::new(true),
+            active_source: Mutex::new(None),
+            smoother: Mutex::new(PositionSmoother::default()),
+            mumble: OnceLock::new(),
+            race_event_subscribers: Mutex::new(vec![]),
+            session_recorder: Mutex::new(None),
+            telemetry_exporter: Mutex::new(None),
+        }
+    }
+}
+
+impl BridgeState {
+    pub fn new() -> SharedState {
+        Arc::new(BridgeState::default())
+    }
+
+    pub fn is_window_visible(&self) -> bool {
+        self.window_visible.load(Ordering::Relaxed)
+    }
+
+    pub fn set_window_visible(&self, visible: bool) {
+        self.window_visible.store(visible, Ordering::Relaxed);
+    }
+
+    pub fn alt_held_at_startup(&self) -> bool {
+        self.alt_held_at_startup.load(Ordering::Relaxed)
+    }
+
+    pub fn set_alt_held_at_startup(&self, held: bool) {
+        self.alt_held_at_startup.store(held, Ordering::Relaxed);
+    }
+
+    /// Returns true for the first request only.
+    pub fn request_shutdown(&self) -> bool {
+        !self.shutdown_requested.swap(true, Ordering::SeqCst)
+    }
+
+    pub fn is_shutting_down(&self) -> bool {
+        self.shutdown_requested.load(Ordering::SeqCst)
+    }
+
+    pub fn link(&self) -> LinkInfo {
+        self.link.read().unwrap().clone()
+    }
+
+    pub fn context(&self) -> String {
+        self.link.read().unwrap().context.clone()
+    }
+
+    pub fn update_link(&self, f: impl FnOnce(&mut LinkInfo)) {
+        f(&mut self.link.write().unwrap());
+    }
+
+    /// Record the context and identity just sent to Mumble.
+    pub fn set_context(&self, context: String, identity: String) {
+        {
+            let mut link = self.link.write().unwrap();
+            link.context = context;
+            link.identity = identity;
+        }
+        self.stats.lock().unwrap().context_updates += 1;
+    }
+
+    pub fn clear_context(&self) {
+        self.link.write().unwrap().context.clear();
+    }
+
+    pub fn next_identity_nonce(&self) -> usize {
+        self.identity_nonce.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// Record positions just sent to Mumble.
+    pub fn record_positions(&self, p: MPos, c: MPos) {
+        self.positions.publish(LastPositions {
+            p,
+            c,
+            at: Instant::now(),
+        });
+        self.stats.lock().unwrap().positions_sent += 1;
+    }
+
+    pub fn last_positions(&self) -> Option<LastPositions> {
+        self.positions.get()
+    }
+
+    pub fn positions_if_newer(&self, seen: &mut u64) -> Option<LastPositions> {
+        self.positions.get_if_newer(seen)
+    }
+
+    pub fn publish_telemetry(&self, frame: TelemetryFrame) {
+        self.telemetry.publish(frame);
+    }
+
+    pub fn telemetry_if_newer(&self, seen: &mut u64) -> Option<TelemetryFrame> {
+        self.telemetry.get_if_newer(seen)
+    }
+
+    pub fn stats(&self) -> BridgeStats {
+        *self.stats.lock().unwrap()
+    }
+
+    pub fn update_stats(&self, f: impl FnOnce(&mut BridgeStats)) {
+        f(&mut self.stats.lock().unwrap());
+    }
+
+    pub fn count_plugin_message(&self, bytes: usize) {
+        let mut stats = self.stats.lock().unwrap();
+        stats.plugin_messages += 1;
+        stats.plugin_bytes += bytes as u64;
+    }
+
+    pub fn count_telemetry_frame(&self) {
+        self.stats.lock().unwrap().telemetry_frames += 1;
+    }
+
+    pub fn set_plugin_handler(&self, handler: Option<NodeHandler<()>>) {
+        *self.plugin_handler.lock().unwrap() = handler;
+    }
+
+    pub fn take_plugin_handler(&self) -> Option<NodeHandler<()>> {
+        self.plugin_handler.lock().unwrap().take()
+    }
+
+    pub fn set_plugin_endpoint(&self, endpoint: Option<Endpoint>) {
+        *self.plugin_endpoint.lock().unwrap() = endpoint;
+    }
+
+    pub fn take_plugin_endpoint(&self) -> Option<Endpoint> {
+        self.plugin_endpoint.lock().unwrap().take()
+    }
+
+    pub(crate) fn telemetry_ctx(&self) -> MutexGuard<'_, MPData> {
+        self.telemetry_ctx.lock().unwrap()
+    }
+
+    pub(crate) fn hybrid(&self) -> MutexGuard<'_, HybridState> {
+        self.hybrid.lock().unwrap()
+    }
+
+    pub(crate) fn observed(&self) -> MutexGuard<'_, Observed> {
+        self.observed.lock().unwrap()
+    }
+
+    pub fn set_mumble(&self, mumble: SharedMumble) {
+        let _ = self.mumble.set(mumble);
+    }
+
+    pub fn mumble(&self) -> Option<&SharedMumble> {
+        self.mumble.get()
+    }
+
+    /// Get a receiver for all race events published from now on.
+    pub fn subscribe_race_events(&self) -> Receiver<RaceEvent> {
+        let (tx, rx) = mpsc::channel();
+        self.race_event_subscribers.lock().unwrap().push(tx);
+        rx
+    }
+
+    /// Log an event and forward it to all subscribers, dropping any that have gone away.
+    pub fn publish_race_event(&self, event: RaceEvent) {
+        log::info!("Race event: {}", event);
+        self.race_event_subscribers
+            .lock()
+            .unwrap()
+            .retain(|tx| tx.send(event.clone()).is_ok());
+    }
+
+    pub(crate) fn session_recorder(&self) -> MutexGuard<'_, Option<SessionRecorder>> {
+        self.session_recorder.lock().unwrap()
+    }
+
+    pub(crate) fn telemetry_exporter(&self) -> MutexGuard<'_, Option<TelemetryExporter>> {
+        self.telemetry_exporter.lock().unwrap()
+    }
+
+    pub(crate) fn smoother(&self) -> MutexGuard<'_, PositionSmoother> {
+        self.smoother.lock().unwrap()
+    }
+
+    pub fn proximity_enabled(&self) -> bool {
+        self.proximity_enabled.load(Ordering::Relaxed)
+    }
+
+    /// Switch proximity chat off (park the player regardless of game state) or back on.
+    pub fn set_proximity_enabled(&self, enabled: bool) {
+        log::info!("Proximity chat {}", if enabled { "enabled" } else { "disabled" });
+        self.proximity_enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    /// The source currently running, if any.
+    pub fn active_source(&self) -> Option<SourceKind> {
+        *self.active_source.lock().unwrap()
+    }
+
+    pub fn set_active_source(&self, kind: Option<SourceKind>) {
+        *self.active_source.lock().unwrap() = kind;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn instances_are_independent() {
+        let a = BridgeState::new();
+        let b = BridgeState::new();
+        a.set_context("TM|a|All".into(), "p|p|0".into());
+        a.record_positions(MPos::example(1.0), MPos::example(2.0));
+        a.set_window_visible(false);
+        a.set_proximity_enabled(false);
+        a.set_active_source(Some(SourceKind::Telemetry));
+
+        assert_eq!(a.context(), "TM|a|All");
+        assert_eq!(a.stats().context_updates, 1);
+        assert_eq!(a.stats().positions_sent, 1);
+        assert!(!a.is_window_visible());
+
+        assert_eq!(b.link(), LinkInfo::default());
+        assert_eq!(b.stats(), BridgeStats::default());
+        assert!(b.last_positions().is_none());
+        assert!(b.is_window_visible());
+        assert!(b.proximity_enabled());
+        assert_eq!(b.active_source(), None);
+    }
+
+    #[test]
+    fn identity_nonce_counts_up() {
+        let s = BridgeState::new();
+        assert_eq!(s.next_identity_nonce(), 0);
+        assert_eq!(s.next_identity_nonce(), 1);
+    }
+
+    /// An hour of plugin traffic at 60 positions and one ping a second, with the GUI draining its
+    /// queue only every half second like it does while hidden: positions never queue up, and the
+    /// queue never holds more than what arrives between two drains.
+    #[test]
+    fn unattended_session_stays_bounded() {
+        use crate::mp_telemetry_data::GameState;
+        use crate::park_rules::{observe, plugin_park_reason, report_park_status, GameStatus};
+        use crate::race_events::RaceEventDetector;
+
+        // an hour of 100 Hz frames with a hidden window: neither the GUI queue nor the race
+        // event subscription is drained
+        const TICKS: u32 = 100 * 60 * 60;
+        let state = BridgeState::new();
+        let (to_gui, from_server) = mpsc::channel();
+        let race_events_rx = state.subscribe_race_events();
+        let mut detector = RaceEventDetector::default();
+        let mut frame = TelemetryFrame::fixture();
+        frame.race.checkpoint_times.clear();
+        let mut park_changes = 0;
+        let mut race_events = 0;
+        let mut last_park = None;
+
+        for tick in 0..TICKS {
+            // back to the menus for a minute every 10 minutes, a checkpoint every 10 seconds
+            let in_menus = tick % 60_000 >= 54_000;
+            frame.game.state = if in_menus { GameState::Menus } else { GameState::Running };
+            frame.update_number = tick;
+            if tick % 1000 == 999 {
+                frame.race.checkpoint_times.push(tick * 10);
+            }
+
+            // what the telemetry source does each frame
+            state.publish_telemetry(frame.clone());
+            for event in detector.update(&frame) {
+                state.publish_race_event(event);
+                race_events += 1;
+            }
+            state.count_telemetry_frame();
+            observe(&state, GameStatus::from(&frame));
+            // and the plugin source, which parks by what telemetry saw
+            let park = plugin_park_reason(&state);
+            report_park_status(&state, park, &to_gui);
+            if park != last_park {
+                park_changes += 1;
+                last_park = park;
+            }
+            state.record_positions(MPos::example(tick as f32), MPos::example(0.0));
+        }
+
+        // only discrete events were queued: the initial park status and its changes
+        assert_eq!(from_server.try_iter().count(), 1 + park_changes);
+        assert!(park_changes <= 12);
+        assert_eq!(race_events_rx.try_iter().count(), race_events);
+        assert!(race_events <= 2 * TICKS as usize / 1000);
+        // and the latest-value cells hold only the newest frame
+        let mut seen = 0;
+        let newest = state.positions_if_newer(&mut seen).unwrap();
+        assert_eq!(newest.p.pos, MPos::example((TICKS - 1) as f32).pos);
+        assert!(state.positions_if_newer(&mut seen).is_none());
+        let mut seen = 0;
+        assert_eq!(state.telemetry_if_newer(&mut seen).unwrap().update_number, TICKS - 1);
+        assert!(state.telemetry_if_newer(&mut seen).is_none());
+        assert_eq!(state.stats().positions_sent, TICKS as u64);
+        assert_eq!(state.stats().telemetry_frames, TICKS as u64);
+    }
+}
@@ -0,0 +1,216 @@
+use cgmath::{InnerSpace, Quaternion, Vector3};
+use mumble_link::Position;
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::app::MUMBLE_SCALE;
+use crate::telemetry_frame::TelemetryFrame;
+
+/// Below this speed (m/s) the car's facing is used as-is for the chase direction.
+const MIN_VELOCITY_BLEND_SPEED: f32 = 5.0;
+/// Closer than this and the camera's facing gets numerically unstable.
+pub const MIN_CHASE_DISTANCE: f32 = 0.5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CameraMode {
+    /// Camera follows behind and above the car.
+    ChaseCam,
+    /// Camera sits in the car, looking where the car faces.
+    FirstPerson,
+}
+
+/// How to estimate a camera position in telemetry mode. Distances are in game units (~meters).
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CameraConfig {
+    pub mode: CameraMode,
+    #[serde(deserialize_with = "deserialize_chase_distance")]
+    pub chase_distance: f32,
+    pub chase_height: f32,
+    /// 0 = chase along the car's facing, 1 = chase along the direction of travel.
+    pub velocity_blend: f32,
+    pub first_person_height: f32,
+}
+
+impl Default for CameraConfig {
+    fn default() -> Self {
+        CameraConfig {
+            mode: CameraMode::ChaseCam,
+            chase_distance: 7.0,
+            chase_height: 2.5,
+            velocity_blend: 0.5,
+            first_person_height: 1.0,
+        }
+    }
+}
+
+fn deserialize_chase_distance<'de, D: Deserializer<'de>>(d: D) -> Result<f32, D::Error> {
+    Ok(f32::deserialize(d)?.max(MIN_CHASE_DISTANCE))
+}
+
+/// Estimate where the game camera is from the car's state, scaled for Mumble.
+pub fn estimate_camera(telemetry: &TelemetryFrame, cfg: &CameraConfig) -> Position {
+    let car_pos: Vector3<f32> = telemetry.object.position.into();
+    let rot_q: Quaternion<f32> = telemetry.object.rotation.into();
+    let car_dir = rot_q * Vector3::unit_z();
+    let car_up = rot_q * Vector3::unit_y();
+
+    let (pos, front, top) = match cfg.mode {
+        CameraMode::FirstPerson => (car_pos + car_up * cfg.first_person_height, car_dir, car_up),
+        CameraMode::ChaseCam => {
+            let velocity: Vector3<f32> = telemetry.object.velocity.into();
+            let speed = velocity.magnitude();
+            let mut heading = car_dir;
+            if speed > MIN_VELOCITY_BLEND_SPEED {
+                let blend = cfg.velocity_blend.clamp(0.0, 1.0);
+                heading = car_dir * (1.0 - blend) + (velocity / speed) * blend;
+            }
+            // chase cams stay level, so only the horizontal heading matters
+            heading.y = 0.0;
+            if heading.magnitude2() < 1e-6 {
+                heading = car_dir;
+            }
+            let world_up = Vector3::unit_y();
+            let pos = car_pos - heading.normalize() * cfg.chase_distance.max(MIN_CHASE_DISTANCE)
+                + world_up * cfg.chase_height;
+            // fall back to the car's own axes rather than normalizing ~zero vectors into NaN
+            let to_car = car_pos - pos;
+            let front = if to_car.magnitude2() < 1e-6 { car_dir } else { to_car.normalize() };
+            let level_up = world_up - front * world_up.dot(front);
+            let top = if level_up.magnitude2() < 1e-6 { car_up } else { level_up.normalize() };
+            (pos, front, top)
+        }
+    };
+
+    Position {
+        position: (pos * MUMBLE_SCALE).into(),
+        front: front.into(),
+        top: top.into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mp_telemetry_data::{Quat, Vec3};
+
+    const IDENTITY: Quat = Quat {
+        w: 1.0,
+        x: 0.0,
+        y: 0.0,
+        z: 0.0,
+    };
+
+    fn vec3(x: f32, y: f32, z: f32) -> Vec3 {
+        Vec3 { x, y, z }
+    }
+
+    fn frame(rotation: Quat, velocity: Vec3) -> TelemetryFrame {
+        let mut frame = TelemetryFrame::fixture();
+        frame.object.position = vec3(32.0, 64.0, 96.0);
+        frame.object.rotation = rotation;
+        frame.object.velocity = velocity;
+        frame
+    }
+
+    fn assert_near(actual: [f32; 3], expected: [f32; 3]) {
+        let close = actual.iter().zip(expected).all(|(a, e)| (a - e).abs() < 1e-4);
+        assert!(close, "{:?} != {:?}", actual, expected);
+    }
+
+    fn assert_finite(p: &Position) {
+        let all = p.position.iter().chain(&p.front).chain(&p.top);
+        assert!(all.copied().all(f32::is_finite));
+    }
+
+    /// Camera offset from the car in game units.
+    fn offset(p: &Position) -> [f32; 3] {
+        [
+            p.position[0] / MUMBLE_SCALE - 32.0,
+            p.position[1] / MUMBLE_SCALE - 64.0,
+            p.position[2] / MUMBLE_SCALE - 96.0,
+        ]
+    }
+
+    #[test]
+    fn chase_cam_sits_behind_and_above_looking_at_the_car() {
+        let cfg = CameraConfig::default();
+        let cam = estimate_camera(&frame(IDENTITY, vec3(0.0, 0.0, 0.0)), &cfg);
+        assert_near(offset(&cam), [0.0, cfg.chase_height, -cfg.chase_distance]);
+
+        let len = cfg.chase_height.hypot(cfg.chase_distance);
+        assert_near(cam.front, [0.0, -cfg.chase_height / len, cfg.chase_distance / len]);
+        assert_near(cam.top, [0.0, cfg.chase_distance / len, cfg.chase_height / len]);
+    }
+
+    #[test]
+    fn chase_direction_blends_in_the_velocity() {
+        let cfg = CameraConfig {
+            velocity_blend: 1.0,
+            chase_height: 0.0,
+            ..Default::default()
+        };
+        // sliding sideways along +x while facing +z
+        let cam = estimate_camera(&frame(IDENTITY, vec3(20.0, 0.0, 0.0)), &cfg);
+        assert_near(offset(&cam), [-cfg.chase_distance, 0.0, 0.0]);
+        assert_near(cam.front, [1.0, 0.0, 0.0]);
+
+        // too slow to trust the direction of travel
+        let cam = estimate_camera(&frame(IDENTITY, vec3(1.0, 0.0, 0.0)), &cfg);
+        assert_near(offset(&cam), [0.0, 0.0, -cfg.chase_distance]);
+    }
+
+    #[test]
+    fn first_person_looks_where_the_car_faces() {
+        let cfg = CameraConfig {
+            mode: CameraMode::FirstPerson,
+            ..Default::default()
+        };
+        let cam = estimate_camera(&frame(IDENTITY, vec3(20.0, 0.0, 0.0)), &cfg);
+        assert_near(offset(&cam), [0.0, cfg.first_person_height, 0.0]);
+        assert_near(cam.front, [0.0, 0.0, 1.0]);
+        assert_near(cam.top, [0.0, 1.0, 0.0]);
+    }
+
+    #[test]
+    fn chase_distance_has_a_minimum() {
+        let cfg = CameraConfig {
+            chase_distance: 0.0,
+            chase_height: 0.0,
+            ..Default::default()
+        };
+        let cam = estimate_camera(&frame(IDENTITY, vec3(0.0, 0.0, 0.0)), &cfg);
+        assert_near(offset(&cam), [0.0, 0.0, -MIN_CHASE_DISTANCE]);
+
+        let loaded: CameraConfig = serde_json::from_str(r#"{"chase_distance": 0.1}"#).unwrap();
+        assert_eq!(loaded.chase_distance, MIN_CHASE_DISTANCE);
+    }
+
+    #[test]
+    fn degenerate_directions_stay_finite() {
+        // nose straight up, flying straight up: no horizontal heading at all
+        let h = std::f32::consts::FRAC_1_SQRT_2;
+        let pointing_up = Quat {
+            w: h,
+            x: -h,
+            y: 0.0,
+            z: 0.0,
+        };
+        let cfg = CameraConfig::default();
+        let cam = estimate_camera(&frame(pointing_up, vec3(0.0, 30.0, 0.0)), &cfg);
+        assert_finite(&cam);
+
+        // and the camera lands right on the car
+        let cfg = CameraConfig {
+            chase_distance: 2.0,
+            chase_height: 2.0,
+            ..Default::default()
+        };
+        let cam = estimate_camera(&frame(pointing_up, vec3(0.0, 30.0, 0.0)), &cfg);
+        assert_finite(&cam);
+        assert_near(offset(&cam), [0.0, 0.0, 0.0]);
+        assert_near(cam.front, [0.0, 1.0, 0.0]);
+
+        let cam = estimate_camera(&frame(IDENTITY, vec3(0.0, 0.0, 0.0)), &cfg);
+        assert_finite(&cam);
+    }
+}
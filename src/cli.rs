@@ -0,0 +1,61 @@
+use std::path::PathBuf;
+
+use crate::telemetry_export::{ExportFormat, ExportOptions};
+
+/// Command line options. Unknown arguments are logged and ignored.
+#[derive(Debug, Default, Clone)]
+pub struct CliArgs {
+    /// Record all plugin protocol traffic to this session file.
+    pub record_session: Option<PathBuf>,
+    /// Replay a recorded session against a running bridge instead of starting the app.
+    pub replay: Option<PathBuf>,
+    /// Address of the bridge to replay against (default `127.0.0.1:46323`).
+    pub replay_addr: Option<String>,
+    /// Export telemetry frames to this file from startup.
+    pub export_telemetry: Option<PathBuf>,
+    /// `csv` or `jsonl`; defaults to the export file's extension.
+    pub export_format: Option<ExportFormat>,
+    /// Write one export file per race run.
+    pub export_split_runs: bool,
+    /// Save one raw telemetry snapshot (plus its parsed JSON) to this file and exit.
+    pub dump_telemetry: Option<PathBuf>,
+}
+
+impl CliArgs {
+    pub fn parse() -> Self {
+        Self::parse_from(std::env::args().skip(1))
+    }
+
+    pub fn parse_from(args: impl IntoIterator<Item = String>) -> Self {
+        let mut ret = CliArgs::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--record-session" => ret.record_session = args.next().map(PathBuf::from),
+                "--replay" => ret.replay = args.next().map(PathBuf::from),
+                "--replay-addr" => ret.replay_addr = args.next(),
+                "--export-telemetry" => ret.export_telemetry = args.next().map(PathBuf::from),
+                "--export-format" => match args.next() {
+                    Some(f) => match ExportFormat::parse(&f) {
+                        Some(fmt) => ret.export_format = Some(fmt),
+                        None => log::warn!("Unknown export format: {}", f),
+                    },
+                    None => log::warn!("--export-format needs a value"),
+                },
+                "--export-split-runs" => ret.export_split_runs = true,
+                "--dump-telemetry" => ret.dump_telemetry = args.next().map(PathBuf::from),
+                _ => log::warn!("Unknown argument: {}", arg),
+            }
+        }
+        ret
+    }
+
+    pub fn export_options(&self) -> Option<ExportOptions> {
+        let path = self.export_telemetry.clone()?;
+        Some(ExportOptions {
+            format: self.export_format.unwrap_or_else(|| ExportFormat::from_path(&path)),
+            path,
+            split_per_run: self.export_split_runs,
+        })
+    }
+}
@@ -0,0 +1,106 @@
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::RwLock;
+
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+
+use crate::autostart::StartupConfig;
+use crate::camera::CameraConfig;
+use crate::log_capture::LogConfig;
+use crate::park_rules::ParkRules;
+use crate::process_watcher::AutoDetectConfig;
+use crate::radar::RadarConfig;
+use crate::smoothing::SmoothingConfig;
+use crate::spectator::SpectatorConfig;
+use crate::tcp_server::PluginServerConfig;
+use crate::source_manager::SourceKind;
+
+const CONFIG_DIR_NAME: &str = "TMMumbleLink";
+const CONFIG_FILE_NAME: &str = "config.json";
+
+/// User settings, persisted as JSON in the platform config dir. Missing fields take their defaults
+/// so older config files keep working.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BridgeConfig {
+    /// Source to start with. If unset, the plugin is used unless alt is held at startup.
+    pub default_source: Option<SourceKind>,
+    pub camera: CameraConfig,
+    pub smoothing: SmoothingConfig,
+    pub park: ParkRules,
+    pub spectator: SpectatorConfig,
+    pub auto_detect: AutoDetectConfig,
+    pub startup: StartupConfig,
+    pub plugin_server: PluginServerConfig,
+    pub logging: LogConfig,
+    pub radar: RadarConfig,
+}
+
+lazy_static! {
+    static ref CONFIG: RwLock<BridgeConfig> = RwLock::new(BridgeConfig::default());
+}
+
+/// `%APPDATA%` on Windows, `$XDG_CONFIG_HOME` or `~/.config` elsewhere.
+pub fn config_dir() -> PathBuf {
+    let base = std::env::var_os("APPDATA")
+        .or_else(|| std::env::var_os("XDG_CONFIG_HOME"))
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|h| PathBuf::from(h).join(".config")))
+        .unwrap_or_else(|| PathBuf::from("."));
+    base.join(CONFIG_DIR_NAME)
+}
+
+/// `%LOCALAPPDATA%` on Windows, `$XDG_DATA_HOME` or `~/.local/share` elsewhere.
+pub fn data_dir() -> PathBuf {
+    let base = std::env::var_os("LOCALAPPDATA")
+        .or_else(|| std::env::var_os("XDG_DATA_HOME"))
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|h| PathBuf::from(h).join(".local").join("share")))
+        .unwrap_or_else(|| PathBuf::from("."));
+    base.join(CONFIG_DIR_NAME)
+}
+
+pub fn config_path() -> PathBuf {
+    config_dir().join(CONFIG_FILE_NAME)
+}
+
+/// Load the config file into the global config. A missing file is written with defaults.
+pub fn load_config() {
+    let path = config_path();
+    match fs::read_to_string(&path) {
+        Ok(s) => match serde_json::from_str::<BridgeConfig>(&s) {
+            Ok(cfg) => {
+                log::info!("Loaded config from {}", path.display());
+                *CONFIG.write().unwrap() = cfg;
+            }
+            Err(e) => log::error!("Invalid config file {}: {}", path.display(), e),
+        },
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            if let Err(e) = save_config() {
+                log::warn!("Failed to write default config: {}", e);
+            }
+        }
+        Err(e) => log::error!("Failed to read config file {}: {}", path.display(), e),
+    }
+}
+
+pub fn save_config() -> io::Result<()> {
+    let path = config_path();
+    fs::create_dir_all(config_dir())?;
+    let s = serde_json::to_string_pretty(&*CONFIG.read().unwrap())?;
+    fs::write(&path, s)
+}
+
+pub fn get_config() -> BridgeConfig {
+    CONFIG.read().unwrap().clone()
+}
+
+/// Modify the global config and save it to disk.
+pub fn update_config(f: impl FnOnce(&mut BridgeConfig)) {
+    f(&mut CONFIG.write().unwrap());
+    if let Err(e) = save_config() {
+        log::error!("Failed to save config: {}", e);
+    }
+}
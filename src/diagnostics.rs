@@ -0,0 +1,229 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use egui_extras::{Column, TableBuilder};
+
+use crate::bridge_state::BridgeStats;
+
+/// How much error/warning history to keep.
+const MAX_HISTORY: usize = 200;
+/// Rates are averaged over this long.
+const RATE_WINDOW: Duration = Duration::from_secs(1);
+const ROW_HEIGHT: f32 = 16.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+#[derive(Debug, Clone)]
+pub struct HistoryEntry {
+    pub at: Instant,
+    pub severity: Severity,
+    pub msg: String,
+}
+
+/// Timestamped errors and warnings, newest last. The newest error stays "current" (shown under
+/// the controls and in the tray) until it's dismissed; the history keeps it either way.
+#[derive(Default)]
+pub struct ErrorHistory {
+    entries: VecDeque<HistoryEntry>,
+    current: Option<HistoryEntry>,
+}
+
+impl ErrorHistory {
+    pub fn error(&mut self, msg: impl Into<String>) {
+        self.push(Severity::Error, msg.into());
+    }
+
+    pub fn warning(&mut self, msg: impl Into<String>) {
+        self.push(Severity::Warning, msg.into());
+    }
+
+    fn push(&mut self, severity: Severity, msg: String) {
+        if self.entries.len() >= MAX_HISTORY {
+            self.entries.pop_front();
+        }
+        let entry = HistoryEntry {
+            at: Instant::now(),
+            severity,
+            msg,
+        };
+        if severity == Severity::Error {
+            self.current = Some(entry.clone());
+        }
+        self.entries.push_back(entry);
+    }
+
+    /// The newest error that hasn't been dismissed.
+    pub fn current(&self) -> Option<&HistoryEntry> {
+        self.current.as_ref()
+    }
+
+    pub fn dismiss(&mut self) {
+        self.current = None;
+    }
+
+    pub fn entries(&self) -> impl DoubleEndedIterator<Item = &HistoryEntry> {
+        self.entries.iter()
+    }
+}
+
+/// Per-second rates of the bridge counters, from snapshots at least `RATE_WINDOW` apart.
+#[derive(Default)]
+pub struct RateMeter {
+    last: Option<(Instant, BridgeStats)>,
+    rates: Rates,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Rates {
+    pub plugin_messages: f32,
+    pub plugin_bytes: f32,
+    pub binary_positions: f32,
+    pub json_positions: f32,
+    pub pings: f32,
+    pub telemetry_frames: f32,
+    pub torn_reads: f32,
+    pub mumble_updates: f32,
+}
+
+impl RateMeter {
+    pub fn sample(&mut self, now: Instant, stats: BridgeStats) {
+        let Some((then, before)) = self.last else {
+            self.last = Some((now, stats));
+            return;
+        };
+        let secs = now.saturating_duration_since(then).as_secs_f32();
+        if secs < RATE_WINDOW.as_secs_f32() {
+            return;
+        }
+        let rate = |f: fn(&BridgeStats) -> u64| f(&stats).saturating_sub(f(&before)) as f32 / secs;
+        self.rates = Rates {
+            plugin_messages: rate(|s| s.plugin_messages),
+            plugin_bytes: rate(|s| s.plugin_bytes),
+            binary_positions: rate(|s| s.binary_positions),
+            json_positions: rate(|s| s.json_positions),
+            pings: rate(|s| s.pings),
+            telemetry_frames: rate(|s| s.telemetry_frames),
+            torn_reads: rate(|s| s.torn_reads),
+            mumble_updates: rate(|s| s.mumble_updates),
+        };
+        self.last = Some((now, stats));
+    }
+
+    pub fn rates(&self) -> Rates {
+        self.rates
+    }
+}
+
+/// The diagnostics panel: counters with their rates, then the error/warning history.
+pub fn ui_diagnostics(ui: &mut egui::Ui, stats: &BridgeStats, rates: &Rates, history: &ErrorHistory) {
+    egui::CollapsingHeader::new("Diagnostics").show(ui, |ui| {
+        let rows: [(&str, u64, Option<f32>); 11] = [
+            ("Plugin messages", stats.plugin_messages, Some(rates.plugin_messages)),
+            ("Plugin bytes", stats.plugin_bytes, Some(rates.plugin_bytes)),
+            ("Binary positions", stats.binary_positions, Some(rates.binary_positions)),
+            ("JSON positions", stats.json_positions, Some(rates.json_positions)),
+            ("Pings", stats.pings, Some(rates.pings)),
+            ("Parse errors", stats.parse_errors, None),
+            ("Telemetry frames", stats.telemetry_frames, Some(rates.telemetry_frames)),
+            ("Torn telemetry reads", stats.torn_reads, Some(rates.torn_reads)),
+            ("Positions sent", stats.positions_sent, None),
+            ("Mumble updates", stats.mumble_updates, Some(rates.mumble_updates)),
+            ("Context updates", stats.context_updates, None),
+        ];
+        ui.push_id("diagnostics_counters", |ui| {
+            TableBuilder::new(ui)
+                .striped(true)
+                .column(Column::auto().at_least(140.0))
+                .column(Column::auto().at_least(70.0))
+                .column(Column::remainder())
+                .vscroll(false)
+                .body(|body| {
+                    body.rows(ROW_HEIGHT, rows.len(), |mut row| {
+                        let (label, total, rate) = rows[row.index()];
+                        row.col(|ui| {
+                            ui.label(label);
+                        });
+                        row.col(|ui| {
+                            ui.label(total.to_string());
+                        });
+                        row.col(|ui| {
+                            if let Some(rate) = rate {
+                                ui.label(format!("{:.1}/s", rate));
+                            }
+                        });
+                    });
+                });
+        });
+
+        ui.separator();
+        ui.label("Errors and warnings:");
+        egui::ScrollArea::vertical()
+            .id_salt("diagnostics_history")
+            .max_height(150.0)
+            .show(ui, |ui| {
+                if history.entries.is_empty() {
+                    ui.label("None");
+                }
+                let now = Instant::now();
+                for entry in history.entries().rev() {
+                    let color = match entry.severity {
+                        Severity::Error => ui.visuals().error_fg_color,
+                        Severity::Warning => ui.visuals().warn_fg_color,
+                    };
+                    ui.horizontal_wrapped(|ui| {
+                        ui.weak(fmt_ago(now.saturating_duration_since(entry.at)));
+                        ui.colored_label(color, &entry.msg);
+                    });
+                }
+            });
+    });
+}
+
+fn fmt_ago(d: Duration) -> String {
+    let secs = d.as_secs();
+    match secs {
+        0..=59 => format!("{}s ago", secs),
+        60..=3599 => format!("{}m {}s ago", secs / 60, secs % 60),
+        _ => format!("{}h {}m ago", secs / 3600, secs / 60 % 60),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rates_are_per_second_over_the_window() {
+        let mut meter = RateMeter::default();
+        let t0 = Instant::now();
+        let mut stats = BridgeStats::default();
+        meter.sample(t0, stats);
+        stats.pings = 5;
+        stats.mumble_updates = 120;
+        // too soon to compute
+        meter.sample(t0 + Duration::from_millis(500), stats);
+        assert_eq!(meter.rates(), Rates::default());
+        meter.sample(t0 + Duration::from_secs(2), stats);
+        assert_eq!(meter.rates().pings, 2.5);
+        assert_eq!(meter.rates().mumble_updates, 60.0);
+    }
+
+    #[test]
+    fn history_is_bounded_and_dismissing_keeps_it() {
+        let mut history = ErrorHistory::default();
+        for i in 0..MAX_HISTORY + 5 {
+            history.warning(format!("w{}", i));
+        }
+        assert!(history.current().is_none());
+        history.error("boom");
+        assert_eq!(history.entries().count(), MAX_HISTORY);
+        assert_eq!(history.current().map(|e| e.msg.as_str()), Some("boom"));
+        history.dismiss();
+        assert!(history.current().is_none());
+        assert_eq!(history.entries().last().map(|e| e.msg.as_str()), Some("boom"));
+    }
+}
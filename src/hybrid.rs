@@ -0,0 +1,199 @@
+use std::time::{Duration, Instant};
+
+use crate::bridge_state::BridgeState;
+
+/// A position source counts as fresh if it produced a live sample within this window.
+const FRESH_WINDOW: Duration = Duration::from_millis(250);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SampleOrigin {
+    Plugin,
+    Telemetry,
+}
+
+/// Arbitration state for hybrid mode, where the plugin and telemetry run side by side.
+/// Positions come from whichever source went live most recently while the other was stale, and
+/// stay with it while it's fresh, so the two streams don't interleave. The plugin's context is
+/// preferred once it has sent server details, since only the plugin knows the real server login
+/// and team.
+#[derive(Debug, Default)]
+pub(crate) struct HybridState {
+    enabled: bool,
+    plugin_connected: bool,
+    plugin_has_server: bool,
+    last_live_plugin: Option<Instant>,
+    last_live_telemetry: Option<Instant>,
+    /// The source whose live samples are being sent.
+    following: Option<SampleOrigin>,
+}
+
+impl HybridState {
+    fn is_fresh(&self, origin: SampleOrigin, now: Instant) -> bool {
+        let last = match origin {
+            SampleOrigin::Plugin => self.last_live_plugin,
+            SampleOrigin::Telemetry => self.last_live_telemetry,
+        };
+        last.is_some_and(|t| now - t < FRESH_WINDOW)
+    }
+
+    fn accept(&mut self, origin: SampleOrigin, live: bool, now: Instant) -> bool {
+        if !self.enabled {
+            return true;
+        }
+        let other = match origin {
+            SampleOrigin::Plugin => SampleOrigin::Telemetry,
+            SampleOrigin::Telemetry => SampleOrigin::Plugin,
+        };
+        if !live {
+            return !self.is_fresh(other, now);
+        }
+        match origin {
+            SampleOrigin::Telemetry => self.last_live_telemetry = Some(now),
+            SampleOrigin::Plugin => self.last_live_plugin = Some(now),
+        }
+        let followed_is_fresh = self.following.is_some_and(|f| self.is_fresh(f, now));
+        if self.following != Some(origin) && followed_is_fresh {
+            return false;
+        }
+        self.following = Some(origin);
+        true
+    }
+}
+
+pub fn set_hybrid_enabled(state: &BridgeState, enabled: bool) {
+    let mut h = state.hybrid();
+    *h = HybridState {
+        enabled,
+        ..Default::default()
+    };
+}
+
+pub fn set_plugin_connected(state: &BridgeState, connected: bool) {
+    let mut h = state.hybrid();
+    h.plugin_connected = connected;
+    if !connected {
+        h.plugin_has_server = false;
+    }
+}
+
+/// The plugin sent server details (true) or left the server (false).
+pub fn set_plugin_has_server(state: &BridgeState, has_server: bool) {
+    state.hybrid().plugin_has_server = has_server;
+}
+
+/// Whether the plugin is connected to our socket server, in any mode that runs it.
+pub fn is_plugin_connected(state: &BridgeState) -> bool {
+    state.hybrid().plugin_connected
+}
+
+/// Whether a position sample from `origin` should be sent to Mumble. `live` is false for parked
+/// samples (not spawned, stalled), which only win if the other source has nothing fresh.
+/// Outside hybrid mode every sample is used.
+pub fn accept_sample(state: &BridgeState, origin: SampleOrigin, live: bool) -> bool {
+    state.hybrid().accept(origin, live, Instant::now())
+}
+
+/// Whether the plugin source may write the Mumble context and identity: always when it runs on
+/// its own, and in hybrid mode only while it owns them. Otherwise the telemetry's context stands.
+pub fn plugin_may_set_context(state: &BridgeState) -> bool {
+    let h = state.hybrid();
+    !h.enabled || (h.plugin_connected && h.plugin_has_server)
+}
+
+/// In hybrid mode the plugin owns the Mumble context and identity once it has told us which
+/// server we're on.
+pub fn plugin_owns_context(state: &BridgeState) -> bool {
+    let h = state.hybrid();
+    h.enabled && h.plugin_connected && h.plugin_has_server
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bridge_state::SharedState;
+    use SampleOrigin::*;
+
+    fn hybrid_state() -> SharedState {
+        let state = BridgeState::new();
+        set_hybrid_enabled(&state, true);
+        state
+    }
+
+    #[test]
+    fn everything_is_accepted_outside_hybrid_mode() {
+        let mut h = HybridState::default();
+        let now = Instant::now();
+        assert!(h.accept(Plugin, true, now));
+        assert!(h.accept(Telemetry, true, now));
+        assert!(h.accept(Telemetry, false, now));
+    }
+
+    #[test]
+    fn follows_the_first_live_source_until_it_goes_stale() {
+        let mut h = HybridState {
+            enabled: true,
+            ..Default::default()
+        };
+        let t0 = Instant::now();
+        assert!(h.accept(Telemetry, true, t0));
+        assert!(!h.accept(Plugin, true, t0 + Duration::from_millis(10)));
+        assert!(h.accept(Telemetry, true, t0 + Duration::from_millis(100)));
+        assert!(!h.accept(Plugin, true, t0 + Duration::from_millis(300)));
+
+        // telemetry last went live at 100ms
+        let stale = t0 + Duration::from_millis(100) + FRESH_WINDOW;
+        assert!(h.accept(Plugin, true, stale));
+        assert_eq!(h.following, Some(Plugin));
+        assert!(!h.accept(Telemetry, true, stale + Duration::from_millis(10)));
+    }
+
+    #[test]
+    fn parked_samples_only_win_without_a_fresh_other_source() {
+        let mut h = HybridState {
+            enabled: true,
+            ..Default::default()
+        };
+        let t0 = Instant::now();
+        assert!(h.accept(Plugin, false, t0));
+        assert!(h.accept(Telemetry, true, t0));
+        assert!(!h.accept(Plugin, false, t0 + Duration::from_millis(10)));
+        assert!(h.accept(Plugin, false, t0 + FRESH_WINDOW));
+    }
+
+    #[test]
+    fn context_ownership() {
+        let state = BridgeState::new();
+        assert!(plugin_may_set_context(&state));
+        assert!(!plugin_owns_context(&state));
+
+        let state = hybrid_state();
+        assert!(!plugin_may_set_context(&state));
+        set_plugin_connected(&state, true);
+        assert!(is_plugin_connected(&state));
+        assert!(!plugin_owns_context(&state), "no server yet");
+        assert!(!plugin_may_set_context(&state));
+
+        set_plugin_has_server(&state, true);
+        assert!(plugin_owns_context(&state));
+        assert!(plugin_may_set_context(&state));
+
+        set_plugin_has_server(&state, false);
+        assert!(!plugin_owns_context(&state));
+
+        set_plugin_has_server(&state, true);
+        set_plugin_connected(&state, false);
+        assert!(!plugin_owns_context(&state), "disconnecting forgets the server");
+        set_plugin_connected(&state, true);
+        assert!(!plugin_owns_context(&state));
+    }
+
+    #[test]
+    fn accept_sample_uses_the_shared_state() {
+        let state = hybrid_state();
+        assert!(accept_sample(&state, Telemetry, true));
+        assert!(!accept_sample(&state, Plugin, true));
+
+        set_hybrid_enabled(&state, false);
+        assert!(accept_sample(&state, Plugin, true));
+    }
+}
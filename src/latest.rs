@@ -0,0 +1,58 @@
+use std::sync::Mutex;
+
+/// A single-slot, watch-style cell for high-frequency data: publishing overwrites the previous
+/// value, so a slow or hidden reader only ever sees the newest one and nothing piles up.
+/// Readers remember the version they last saw to tell whether there is something new.
+pub struct Latest<T> {
+    slot: Mutex<(u64, Option<T>)>,
+}
+
+impl<T> Default for Latest<T> {
+    fn default() -> Self {
+        Latest {
+            slot: Mutex::new((0, None)),
+        }
+    }
+}
+
+impl<T: Clone> Latest<T> {
+    pub fn publish(&self, value: T) {
+        let mut slot = self.slot.lock().unwrap();
+        slot.0 += 1;
+        slot.1 = Some(value);
+    }
+
+    pub fn get(&self) -> Option<T> {
+        self.slot.lock().unwrap().1.clone()
+    }
+
+    /// The value, if one was published since `seen`; updates `seen`.
+    pub fn get_if_newer(&self, seen: &mut u64) -> Option<T> {
+        let slot = self.slot.lock().unwrap();
+        if slot.0 == *seen {
+            return None;
+        }
+        *seen = slot.0;
+        slot.1.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_only_the_newest_value() {
+        let cell = Latest::default();
+        let mut seen = 0;
+        assert_eq!(cell.get_if_newer(&mut seen), None);
+        for i in 0..1000 {
+            cell.publish(i);
+        }
+        assert_eq!(cell.get_if_newer(&mut seen), Some(999));
+        assert_eq!(cell.get_if_newer(&mut seen), None);
+        assert_eq!(cell.get(), Some(999));
+        cell.publish(5);
+        assert_eq!(cell.get_if_newer(&mut seen), Some(5));
+    }
+}
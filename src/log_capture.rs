@@ -0,0 +1,245 @@
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use lazy_static::lazy_static;
+use log::{Level, Log, Metadata, Record};
+use serde::{Deserialize, Serialize};
+
+use crate::config::data_dir;
+
+/// Records kept for the log panel.
+const MAX_ENTRIES: usize = 2000;
+const LOG_FILE_STEM: &str = "tm-mumble-link";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LogConfig {
+    /// Also write the log to rotating files in the data dir.
+    pub write_files: bool,
+    /// Start a new file once the current one reaches this size.
+    pub max_file_kb: u32,
+    /// Old files kept besides the current one.
+    pub max_old_files: u32,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        LogConfig {
+            write_files: false,
+            max_file_kb: 1024,
+            max_old_files: 4,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LogEntry {
+    pub time: SystemTime,
+    pub level: Level,
+    pub target: String,
+    pub msg: String,
+}
+
+impl LogEntry {
+    /// One line, as written to the log file.
+    pub fn line(&self) -> String {
+        format!(
+            "{} {:<5} {}: {}",
+            humantime::format_rfc3339_seconds(self.time),
+            self.level,
+            self.target,
+            self.msg
+        )
+    }
+}
+
+/// Where the log files go.
+pub fn log_dir() -> PathBuf {
+    data_dir().join("logs")
+}
+
+lazy_static! {
+    /// The newest records with a version that counts every record, so readers can tell
+    /// whether anything was added.
+    static ref ENTRIES: Mutex<(u64, VecDeque<LogEntry>)> = Mutex::new((0, VecDeque::new()));
+    static ref LOG_FILE: Mutex<Option<RotatingFile>> = Mutex::new(None);
+}
+
+/// Sends records to stderr as before (filtered by `RUST_LOG`) and keeps them for the log panel
+/// and the log files.
+struct CaptureLogger {
+    stderr: env_logger::Logger,
+}
+
+impl Log for CaptureLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.stderr.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.stderr.matches(record) {
+            return;
+        }
+        self.stderr.log(record);
+        let entry = LogEntry {
+            time: SystemTime::now(),
+            level: record.level(),
+            target: record.target().to_string(),
+            msg: record.args().to_string(),
+        };
+        if let Some(file) = LOG_FILE.lock().unwrap().as_mut() {
+            // logging from here would recurse
+            if let Err(e) = file.write_line(&entry.line()) {
+                eprintln!("Failed to write log file: {}", e);
+            }
+        }
+        let mut entries = ENTRIES.lock().unwrap();
+        if entries.1.len() >= MAX_ENTRIES {
+            entries.1.pop_front();
+        }
+        entries.1.push_back(entry);
+        entries.0 += 1;
+    }
+
+    fn flush(&self) {
+        self.stderr.flush();
+        if let Some(file) = LOG_FILE.lock().unwrap().as_mut() {
+            let _ = file.file.flush();
+        }
+    }
+}
+
+/// Install the logger. `RUST_LOG` still controls what's logged, defaulting to `info`.
+pub fn init() {
+    let stderr =
+        env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).build();
+    // otherwise `log` drops everything below its default maximum before we see it
+    let max_level = stderr.filter();
+    log::set_boxed_logger(Box::new(CaptureLogger { stderr })).expect("logger already set");
+    log::set_max_level(max_level);
+}
+
+/// Start, stop or reconfigure the log files.
+pub fn apply_config(cfg: &LogConfig) {
+    let opened = {
+        let mut file = LOG_FILE.lock().unwrap();
+        match file.as_mut() {
+            _ if !cfg.write_files => {
+                *file = None;
+                return;
+            }
+            Some(f) => {
+                f.cfg = *cfg;
+                return;
+            }
+            None => RotatingFile::open(log_dir(), *cfg).map(|f| *file = Some(f)),
+        }
+    };
+    // logged once the lock is released, since logging writes to the file too
+    match opened {
+        Ok(()) => log::info!("Writing log files to {}", log_dir().display()),
+        Err(e) => log::error!("Failed to open a log file in {}: {}", log_dir().display(), e),
+    }
+}
+
+/// All kept records, if any were logged since `seen`; updates `seen`.
+pub fn entries_if_newer(seen: &mut u64) -> Option<Vec<LogEntry>> {
+    let entries = ENTRIES.lock().unwrap();
+    if entries.0 == *seen {
+        return None;
+    }
+    *seen = entries.0;
+    Some(entries.1.iter().cloned().collect())
+}
+
+/// `tm-mumble-link.log`, rotated to `tm-mumble-link.1.log`, `.2.log`, ... when it gets too big.
+struct RotatingFile {
+    dir: PathBuf,
+    file: File,
+    size: u64,
+    cfg: LogConfig,
+}
+
+impl RotatingFile {
+    fn open(dir: PathBuf, cfg: LogConfig) -> io::Result<RotatingFile> {
+        fs::create_dir_all(&dir)?;
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(file_path(&dir, 0))?;
+        let size = file.metadata()?.len();
+        Ok(RotatingFile {
+            dir,
+            file,
+            size,
+            cfg,
+        })
+    }
+
+    fn write_line(&mut self, line: &str) -> io::Result<()> {
+        if self.size > 0 && self.size + line.len() as u64 + 1 > self.cfg.max_file_kb as u64 * 1024 {
+            self.rotate()?;
+        }
+        writeln!(self.file, "{}", line)?;
+        self.size += line.len() as u64 + 1;
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        let _ = fs::remove_file(file_path(&self.dir, self.cfg.max_old_files));
+        for i in (0..self.cfg.max_old_files).rev() {
+            let from = file_path(&self.dir, i);
+            if from.exists() {
+                fs::rename(from, file_path(&self.dir, i + 1))?;
+            }
+        }
+        self.file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(file_path(&self.dir, 0))?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+fn file_path(dir: &Path, index: u32) -> PathBuf {
+    match index {
+        0 => dir.join(format!("{}.log", LOG_FILE_STEM)),
+        i => dir.join(format!("{}.{}.log", LOG_FILE_STEM, i)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rotates_and_keeps_only_the_configured_files() {
+        let dir = std::env::temp_dir().join(format!("tm-mumble-link-log-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let cfg = LogConfig {
+            write_files: true,
+            max_file_kb: 1,
+            max_old_files: 2,
+        };
+        let mut file = RotatingFile::open(dir.clone(), cfg).unwrap();
+        let line = "x".repeat(299);
+        // 300 bytes a line, so 3 lines per 1 KiB file
+        for _ in 0..12 {
+            file.write_line(&line).unwrap();
+        }
+        drop(file);
+
+        assert_eq!(fs::metadata(file_path(&dir, 0)).unwrap().len(), 900);
+        assert_eq!(fs::metadata(file_path(&dir, 1)).unwrap().len(), 900);
+        assert_eq!(fs::metadata(file_path(&dir, 2)).unwrap().len(), 900);
+        assert!(!file_path(&dir, 3).exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,103 @@
+use log::Level;
+
+use crate::config::{get_config, update_config};
+use crate::log_capture::{apply_config, entries_if_newer, log_dir, LogEntry};
+
+const LEVELS: [Level; 5] = [Level::Error, Level::Warn, Level::Info, Level::Debug, Level::Trace];
+
+/// The log panel: the captured log with a level filter and search, plus the log file settings.
+pub struct LogView {
+    seen: u64,
+    entries: Vec<LogEntry>,
+    /// Show this level and more severe ones.
+    max_level: Level,
+    search: String,
+}
+
+impl Default for LogView {
+    fn default() -> Self {
+        LogView {
+            seen: 0,
+            entries: Vec::new(),
+            max_level: Level::Info,
+            search: String::new(),
+        }
+    }
+}
+
+impl LogView {
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        egui::CollapsingHeader::new("Log").show(ui, |ui| {
+            if let Some(entries) = entries_if_newer(&mut self.seen) {
+                self.entries = entries;
+            }
+            ui.horizontal(|ui| {
+                egui::ComboBox::from_id_salt("log_level")
+                    .selected_text(self.max_level.as_str())
+                    .show_ui(ui, |ui| {
+                        for level in LEVELS {
+                            ui.selectable_value(&mut self.max_level, level, level.as_str());
+                        }
+                    });
+                ui.add(egui::TextEdit::singleline(&mut self.search).hint_text("Search"));
+            });
+
+            let search = self.search.to_lowercase();
+            let shown: Vec<&LogEntry> = self
+                .entries
+                .iter()
+                .filter(|e| e.level <= self.max_level)
+                .filter(|e| search.is_empty() || e.line().to_lowercase().contains(&search))
+                .collect();
+            ui.horizontal(|ui| {
+                ui.label(format!("{} of {} lines", shown.len(), self.entries.len()));
+                if ui.button("Copy").clicked() {
+                    let text: Vec<String> = shown.iter().map(|e| e.line()).collect();
+                    ui.ctx().copy_text(text.join("\n"));
+                }
+            });
+
+            let row_height = ui.text_style_height(&egui::TextStyle::Monospace);
+            egui::ScrollArea::both()
+                .id_salt("log_lines")
+                .max_height(200.0)
+                .stick_to_bottom(true)
+                .show_rows(ui, row_height, shown.len(), |ui, rows| {
+                    for entry in &shown[rows] {
+                        let text = egui::RichText::new(entry.line()).monospace();
+                        match entry.level {
+                            Level::Error => ui.colored_label(ui.visuals().error_fg_color, text),
+                            Level::Warn => ui.colored_label(ui.visuals().warn_fg_color, text),
+                            _ => ui.label(text),
+                        };
+                    }
+                });
+
+            ui.separator();
+            ui_log_file_settings(ui);
+        });
+    }
+}
+
+fn ui_log_file_settings(ui: &mut egui::Ui) {
+    let mut logging = get_config().logging;
+    ui.checkbox(&mut logging.write_files, "Write log files");
+    ui.add_enabled_ui(logging.write_files, |ui| {
+        ui.horizontal(|ui| {
+            ui.label("Max size (KiB):");
+            ui.add(egui::DragValue::new(&mut logging.max_file_kb).range(16..=65536));
+            ui.label("Old files kept:");
+            ui.add(egui::DragValue::new(&mut logging.max_old_files).range(0..=50));
+        });
+        ui.horizontal(|ui| {
+            ui.label(format!("In {}", log_dir().display()));
+            if ui.small_button("Copy path").clicked() {
+                ui.ctx().copy_text(log_dir().display().to_string());
+            }
+        });
+    });
+    if logging != get_config().logging {
+        update_config(|c| c.logging = logging);
+        apply_config(&logging);
+    }
+}
@@ -1,38 +1,58 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-use std::{borrow::Borrow, sync::Mutex};
 #[allow(unused_imports)]
 use std::thread;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
 
 use crate::app::MumbleBridgeApp;
+use crate::bridge_state::BridgeState;
 use app::{FromGuiToServer, ToGUI};
+use cli::CliArgs;
 // use crate::error::AppError;
 use eframe::Renderer;
 use egui::{vec2, Context};
 use tcp_server::{FromTM, MPos};
-#[allow(unused_imports)]
-use tcp_server::{shutdown_tcp_server, TCP_HANDLER};
 // use egui::mutex::RwLock;
 use lazy_static::lazy_static;
 // use shmem_bind::{self as shmem, ShmemBox, ShmemError};
 // use sysinfo::{ProcessRefreshKind, System};
-use tray_icon::{
-    menu::{IsMenuItem, Menu, MenuEvent, MenuItem},
-    Icon, MouseButton, MouseButtonState, TrayIconBuilder, TrayIconEvent,
-};
 use util::is_alt_held;
 // #[cfg(windows)]
 // use windows::Win32::Foundation::HWND;
 // use winit::raw_window_handle::{HasWindowHandle, Win32WindowHandle, WaylandWindowHandle, XlibWindowHandle};
 
 mod app;
+mod autostart;
+mod bridge_state;
+mod camera;
+mod cli;
+mod config;
+mod diagnostics;
+mod hybrid;
+mod latest;
+mod log_capture;
+mod log_view;
 mod tcp_server;
+mod telemetry_export;
+mod telemetry_frame;
+mod telemetry_view;
+mod tray;
 mod maniaplanet_telemetry;
 pub mod mp_telemetry_data;
+mod mp_telemetry_parse;
+mod park_rules;
+mod process_watcher;
+mod race_events;
+mod radar;
+mod session_log;
+mod shutdown;
+mod single_instance;
+mod smoothing;
+mod source_manager;
+mod spectator;
 mod util;
 
-pub static VISIBLE: Mutex<bool> = Mutex::new(true);
-pub static ALT_HELD_AT_STARTUP: Mutex<bool> = Mutex::new(false);
 // #[cfg(windows)]
 // pub static WINDOW_HANDLE: RwLock<Option<Win32WindowHandle>> = RwLock::new();
 // #[cfg(not(windows))]
@@ -46,12 +66,70 @@ pub static ALT_HELD_AT_STARTUP: Mutex<bool> = Mutex::new(false);
 // }
 
 fn main() {
-    let env_ll = std::env::var("RUST_LOG").unwrap_or_else(|_| "info".to_string());
-    std::env::set_var("RUST_LOG", env_ll);
-    env_logger::init();
-    log::set_max_level(log::LevelFilter::Info);
+    log_capture::init();
+
+    config::load_config();
+    log_capture::apply_config(&config::get_config().logging);
+
+    let args = CliArgs::parse();
+    if let Some(path) = args.replay.as_ref() {
+        let addr = args
+            .replay_addr
+            .clone()
+            .unwrap_or_else(|| format!("127.0.0.1:{}", tcp_server::DEFAULT_PORT));
+        if let Err(e) = session_log::replay_session(path, &addr) {
+            log::error!("Replay failed: {}", e);
+        }
+        return;
+    }
+    if let Some(path) = args.dump_telemetry.as_ref() {
+        match maniaplanet_telemetry::dump_telemetry(path) {
+            Ok(()) => log::info!("Saved telemetry snapshot to {}", path.display()),
+            Err(e) => log::error!("Failed to dump telemetry: {}", e),
+        }
+        return;
+    }
 
-    *ALT_HELD_AT_STARTUP.lock().unwrap() = is_alt_held();
+    // before anything touches files the running copy may be using
+    let instance = match single_instance::claim_instance() {
+        Ok(single_instance::Instance::First(guard)) => Some(guard),
+        Ok(single_instance::Instance::Existing { shown: true }) => {
+            log::info!("TM to Mumble Link is already running; showing its window instead");
+            return;
+        }
+        Ok(single_instance::Instance::Existing { shown: false }) => {
+            log::info!("TM to Mumble Link is already running");
+            util::show_message(
+                "TM to Mumble Link",
+                "TM to Mumble Link is already running. Look for its icon in the system tray.",
+            );
+            return;
+        }
+        Err(e) => {
+            log::warn!("Failed to check for another running bridge: {}", e);
+            None
+        }
+    };
+
+    let state = BridgeState::new();
+    state.set_alt_held_at_startup(is_alt_held());
+
+    if let Some(path) = args.record_session.as_ref() {
+        if let Err(e) = session_log::start_recording(&state, path) {
+            log::error!("Failed to start recording session to {}: {}", path.display(), e);
+        }
+    }
+    if let Some(opts) = args.export_options() {
+        telemetry_export::start_export(&state, opts);
+    }
+
+    let startup = config::get_config().startup;
+    if startup.run_at_login {
+        // keep the entry pointing at this executable in case it moved
+        if let Err(e) = autostart::set_run_at_login(true) {
+            log::warn!("Failed to update autostart entry: {}", e);
+        }
+    }
 
     log::info!("Starting TM to Mumble Link");
 
@@ -67,12 +145,18 @@ fn main() {
         .with_maximize_button(false);
     nat_opts.renderer = Renderer::Wgpu;
 
-    if *ALT_HELD_AT_STARTUP.lock().unwrap() {
+    if state.alt_held_at_startup() {
         nat_opts.viewport = nat_opts.viewport
             .with_inner_size(vec2(400.0, 840.0))
             .with_min_inner_size(vec2(400.0, 840.0));
     }
 
+    if startup.start_hidden {
+        log::info!("Starting hidden in the tray");
+        nat_opts.viewport = nat_opts.viewport.with_visible(false);
+        state.set_window_visible(false);
+    }
+
     // let (from_tm_tx, from_tm_rx) = std::sync::mpsc::channel::<FromTM>();
     // let (to_tm_tx, to_tm_rx) = std::sync::mpsc::channel::<ToTM>();
 
@@ -92,40 +176,33 @@ fn main() {
     let (to_gui_tx, mut to_gui_rx) = std::sync::mpsc::channel::<ToGUI>();
     let (from_gui_tx, from_gui_rx) = std::sync::mpsc::channel::<FromGuiToServer>();
 
-    let icon =
-        Icon::from_rgba(ICON_DATA.0.clone(), ICON_DATA.1, ICON_DATA.2).expect("to create icon");
-    let menu_entries = generate_menu_entries();
-    let tray_menu = Menu::with_items(
-        &menu_entries
-            .iter()
-            .map(|mi| mi.borrow())
-            .collect::<Vec<_>>(),
-    )
-    .expect("to create menu");
-    let _tray_icon = TrayIconBuilder::new()
-        .with_icon(icon)
-        .with_tooltip("TM to Mumble Link")
-        .with_menu(Box::new(tray_menu))
-        .build()
-        .expect("to build tray icon");
+    let watcher_stop = Arc::new(AtomicBool::new(false));
+    let watcher = process_watcher::spawn_process_watcher(
+        to_gui_tx.clone(),
+        from_gui_tx.clone(),
+        state.clone(),
+        watcher_stop.clone(),
+    );
 
     let to_gui_tx2 = to_gui_tx.clone();
-    std::thread::spawn(|| {
-        tcp_server::server_main("", 0, to_gui_tx2, from_gui_rx);
+    let server_state = state.clone();
+    let server_thread = std::thread::spawn(|| {
+        tcp_server::server_main("", 0, server_state, to_gui_tx2, from_gui_rx);
     });
 
-    let (shutdown_tx, _shutdown_rx) = std::sync::mpsc::channel::<()>();
+    let exit_trigger = shutdown::ShutdownTrigger::new(state.clone());
+    shutdown::install_signal_handlers(exit_trigger.clone());
+    shutdown::install_panic_hook(exit_trigger.clone());
 
-    // 'outer: while shutdown_rx.try_recv().is_err() {
-    // shutdown_tx.send(()).expect("to send shutdown signal");
     let borrowed_to_gui_rx = &mut to_gui_rx;
     // let cloned_to_gui_tx = to_gui_tx.clone();
-    let cloned_shutdown_tx = shutdown_tx.clone();
+    let tray_from_gui_tx = from_gui_tx.clone();
+    let tray = tray::Tray::new(&state);
     eframe::run_native(
         "TM to Mumble Link",
         nat_opts.clone(),
         // tray icon stuff via: https://github.com/emilk/egui/discussions/737#discussioncomment-8830140
-        Box::new(|_cc| {
+        Box::new(|cc| {
             // if windows
 
             // #[cfg(windows)]
@@ -162,58 +239,22 @@ fn main() {
                 // }
             }
 
-            MenuEvent::set_event_handler(Some(move |event: MenuEvent| {
-                // println!("MenuEvent: {:?}", event);
-                // cloned_to_gui_tx.send(ToGUI::TaskBarIconMsg(format!("MenuEvent: {:?}", event))).expect("to send to gui");
-                let MenuEvent { id } = event;
-                match id.0.as_str() {
-                    MID_SHOW => {
-                        // show_window(&context);
-                    }
-                    MID_HIDE => {
-                        // hide_window(&context);
-                        // cloned_to_gui_tx.send(ToGUI::HideMainWindow()).expect("to send to gui");
-                    }
-                    MID_EXIT => {
-                        std::process::exit(0);
-                    }
-                    _ => {
-                        eprintln!("Unknown menu id: {}", id.0);
-                    }
-                }
-            }));
-
-            // tray-icon crate
-            // https://docs.rs/tray-icon/0.12.0/tray_icon/struct.TrayIconEvent.html#method.set_event_handler
-            TrayIconEvent::set_event_handler(Some(move |event: TrayIconEvent| {
-                // println!("TrayIconEvent: {:?}", event);
-                // let _ = cloned_to_gui_tx.send(ToGUI::TaskBarIconMsg(format!("TrayIconEvent: {:?}", event))).expect("to send to gui");
-                let (_id, _pos, _rect, btn, btn_state) = match event {
-                    TrayIconEvent::Click {
-                        id,
-                        position,
-                        rect,
-                        button,
-                        button_state,
-                    } => (id, position, rect, button, button_state),
-                    _ => {
-                        return;
-                    }
-                };
-
-                if btn_state == MouseButtonState::Down {
-                    match btn {
-                        MouseButton::Left => {}
-                        MouseButton::Right => {}
-                        _ => {}
-                    }
-                }
-            }));
-            Ok(Box::new(MumbleBridgeApp::new(
-                borrowed_to_gui_rx,
-                from_gui_tx.clone(),
-                cloned_shutdown_tx,
-            )))
+            exit_trigger.set_context(cc.egui_ctx.clone());
+            tray::set_event_handlers(
+                cc.egui_ctx.clone(),
+                tray_from_gui_tx,
+                state.clone(),
+                exit_trigger.clone(),
+            );
+            tray::spawn_refresh_timer(cc.egui_ctx.clone(), state.clone());
+            if let Some(guard) = instance.as_ref() {
+                guard.set_window(cc.egui_ctx.clone(), state.clone());
+            }
+
+            Ok(Box::new(
+                MumbleBridgeApp::new(borrowed_to_gui_rx, from_gui_tx.clone(), state.clone())
+                    .with_tray(tray),
+            ))
         }),
     )
     .expect("to run the app");
@@ -223,16 +264,7 @@ fn main() {
     // MenuEvent::set_event_handler(Some(null_menu_handler));
 
     log::info!("App closed");
-    #[cfg(not(debug_assertions))]
-    {
-        shutdown_tcp_server();
-        log::info!("TCP server stopped. Sleeping 1ms before exit.");
-        thread::sleep(std::time::Duration::from_millis(1));
-    }
-    #[cfg(debug_assertions)]
-    {
-        log::info!("TCP server not stopped in debug mode to try and reproduce crashes. `main` ending now.");
-    }
+    shutdown::shutdown(&state, &from_gui_tx, server_thread, watcher, &watcher_stop);
     // set_window_visible((), false);
     // return;
     // while !is_window_visible() {
@@ -246,10 +278,6 @@ fn main() {
     // }
 }
 
-pub fn is_window_visible() -> bool {
-    *VISIBLE.lock().unwrap()
-}
-
 // #[cfg(windows)]
 // pub fn hide_window(handle: HWND) {
 //     set_window_visible(handle, false);
@@ -287,40 +315,30 @@ pub fn get_window_handle() -> () {
 }
 
 // #[cfg(not(windows))]
-pub fn hide_window(ctx: &Context) {
-    set_window_visible(ctx, false);
+pub fn hide_window(ctx: &Context, state: &BridgeState) {
+    set_window_visible(ctx, state, false);
 }
 
 // #[cfg(not(windows))]
-pub fn show_window(ctx: &Context) {
-    set_window_visible(ctx, true);
+pub fn show_window(ctx: &Context, state: &BridgeState) {
+    set_window_visible(ctx, state, true);
 }
 
 // #[cfg(not(windows))]
-pub fn set_window_visible(ctx: &Context, visible: bool) {
+pub fn set_window_visible(ctx: &Context, state: &BridgeState, visible: bool) {
     // if let Some(wh) = WAYLAND_HANDLE.get() {
 
     // }
     ctx.send_viewport_cmd(egui::ViewportCommand::Visible(visible));
+    // may be called from the tray thread, so make sure the command gets processed
+    ctx.request_repaint();
     println!("Setting window visible: {}", visible);
-    *VISIBLE.lock().unwrap() = visible;
-    println!("Window visible: {}", *VISIBLE.lock().unwrap());
-}
-
-const MID_SHOW: &str = "1";
-const MID_HIDE: &str = "2";
-const MID_EXIT: &str = "3";
-
-pub fn generate_menu_entries<'a>() -> Vec<Box<dyn IsMenuItem>> {
-    vec![
-        // Box::new(MenuItem::with_id(MID_SHOW, "Show", true, None)),
-        // Box::new(MenuItem::with_id(MID_HIDE, "Hide", true, None)),
-        Box::new(MenuItem::with_id(MID_EXIT, "Exit", true, None)),
-    ]
+    state.set_window_visible(visible);
+    println!("Window visible: {}", state.is_window_visible());
 }
 
 lazy_static! {
-    static ref ICON_DATA: (Vec<u8>, u32, u32) = {
+    pub(crate) static ref ICON_DATA: (Vec<u8>, u32, u32) = {
         let icon = include_bytes!("../assets/icon.ico");
         let image = image::load_from_memory(icon)
             .expect("Failed to open icon path")
@@ -1,27 +1,77 @@
 use std::ffi::CString;
-use std::ops::Deref;
+use std::fs;
+use std::fmt::{self, Display, Formatter};
+use std::mem::offset_of;
+use std::path::Path;
 use std::time::{Duration, Instant};
-use std::{io, thread};
-use std::sync::mpsc::{self, SendError, Sender};
-use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+use std::sync::mpsc::{SendError, Sender};
+use std::sync::atomic::{AtomicBool, Ordering};
 
 use cgmath::{Vector3};
 use cgmath::Quaternion;
-use lazy_static::lazy_static;
 use md5::{Md5, Digest};
-use mumble_link::{MumbleLink, Position};
+use mumble_link::Position;
 use windows::core::PCSTR;
 use windows::Win32::Foundation::{CloseHandle, HANDLE};
 use windows::Win32::System::Memory::{
-    MapViewOfFile, OpenFileMappingA, UnmapViewOfFile, FILE_MAP_READ, MEMORY_MAPPED_VIEW_ADDRESS,
+    MapViewOfFile, OpenFileMappingA, UnmapViewOfFile, VirtualQuery, FILE_MAP_READ,
+    MEMORY_BASIC_INFORMATION, MEMORY_MAPPED_VIEW_ADDRESS,
 };
 
 use crate::app::{ToGUI, MUMBLE_SCALE};
-use crate::mp_telemetry_data::{AsCStrSlice, STelemetry};
-use crate::tcp_server::{FromTM, LAST_CONTEXT};
+use crate::bridge_state::BridgeState;
+use crate::camera::estimate_camera;
+use crate::config::get_config;
+use crate::mp_telemetry_data::{RaceState, STelemetry};
+use crate::mp_telemetry_parse::{TelemetryParseError, STELEMETRY_SIZE};
+use crate::hybrid::{accept_sample, plugin_owns_context, SampleOrigin};
+use crate::park_rules::{observe, report_park_status, GameStatus, ParkReason};
+use crate::race_events::RaceEventDetector;
+use crate::smoothing::{update_positions, PositionSample};
+use crate::source_manager::sleep_unless_stopped;
+use crate::spectator::{spectator_context_suffix, SpectatorListenAt, SpectatorMode};
+use crate::telemetry_frame::TelemetryFrame;
+use crate::telemetry_export::export_frame;
+use crate::tcp_server::{FromTM, SharedMumble};
+
+
+#[derive(Debug)]
+pub enum ReadTelemetryError {
+    Windows(windows_core::Error),
+    Parse(TelemetryParseError),
+    /// The game updated the snapshot while we were copying it; read again.
+    Torn,
+}
+
+impl From<windows_core::Error> for ReadTelemetryError {
+    fn from(e: windows_core::Error) -> Self {
+        ReadTelemetryError::Windows(e)
+    }
+}
+
+impl From<TelemetryParseError> for ReadTelemetryError {
+    fn from(e: TelemetryParseError) -> Self {
+        ReadTelemetryError::Parse(e)
+    }
+}
 
+impl Display for ReadTelemetryError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            ReadTelemetryError::Windows(e) => write!(f, "{}", e),
+            ReadTelemetryError::Parse(e) => write!(f, "{}", e),
+            ReadTelemetryError::Torn => write!(f, "telemetry changed while reading it"),
+        }
+    }
+}
 
-pub fn read_telemetry() -> windows_core::Result<STelemetry> {
+pub fn read_telemetry() -> Result<STelemetry, ReadTelemetryError> {
+    Ok(STelemetry::from_bytes(&read_raw_telemetry()?)?)
+}
+
+/// The snapshot as the game wrote it, unparsed.
+pub fn read_raw_telemetry() -> Result<[u8; STELEMETRY_SIZE], ReadTelemetryError> {
     let t_data;
 
     unsafe {
@@ -38,13 +88,20 @@ pub fn read_telemetry() -> windows_core::Result<STelemetry> {
                 "Failed to open file mapping: GetLastError() = {}",
                 windows::core::Error::from_win32()
             );
-            return Err(windows::core::Error::from_win32());
+            return Err(windows::core::Error::from_win32().into());
         }
 
         // Map a view of the file into our address space.
         let view = MapViewOfFile(file_mapping, FILE_MAP_READ, 0, 0, 0);
 
-        t_data = read_mp_telemetry(view)?;
+        if view.Value.is_null() {
+            let e = windows::core::Error::from_win32();
+            let _ = CloseHandle(file_mapping);
+            return Err(e.into());
+        }
+
+        // don't bail before the view is unmapped
+        t_data = read_mp_telemetry(view);
 
         // Clean up.
         if let Err(e) = UnmapViewOfFile(view) {
@@ -55,21 +112,65 @@ pub fn read_telemetry() -> windows_core::Result<STelemetry> {
         }
     }
 
-    Ok(t_data)
+    t_data
+}
+
+/// Save the current snapshot to `path` as raw bytes, with what it parses to next to it as
+/// `<path>.json`. These are the captures `mp_telemetry_parse`'s tests check against.
+pub fn dump_telemetry(path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let bytes = read_raw_telemetry().map_err(|e| e.to_string())?;
+    let parsed = STelemetry::from_bytes(&bytes)?;
+    fs::write(path, bytes)?;
+    fs::write(path.with_extension("json"), serde_json::to_string_pretty(&parsed)?)?;
+    Ok(())
+}
+
+/// Copy the snapshot out of the mapping, checking `update_number` didn't change meanwhile.
+/// Safety: `view` must be a non-null view returned by `MapViewOfFile`.
+unsafe fn read_mp_telemetry(
+    view: MEMORY_MAPPED_VIEW_ADDRESS,
+) -> Result<[u8; STELEMETRY_SIZE], ReadTelemetryError> {
+    // an older or foreign mapping could be smaller than the struct we expect
+    let len = mapped_len(view);
+    if len < STELEMETRY_SIZE {
+        return Err(TelemetryParseError::TooShort { len, expected: STELEMETRY_SIZE }.into());
+    }
+    let src = view.Value as *const u8;
+    let update_number = || {
+        std::ptr::read_volatile(src.add(offset_of!(STelemetry, update_number)) as *const [u8; 4])
+    };
+    let before = update_number();
+    let mut bytes = [0u8; STELEMETRY_SIZE];
+    std::ptr::copy_nonoverlapping(src, bytes.as_mut_ptr(), STELEMETRY_SIZE);
+    if update_number() != before {
+        return Err(ReadTelemetryError::Torn);
+    }
+    Ok(bytes)
 }
 
-unsafe fn read_mp_telemetry(view: MEMORY_MAPPED_VIEW_ADDRESS) -> windows_core::Result<STelemetry> {
-    let telemetry_ptr = view.Value as *const STelemetry;
-    let telemetry_val: STelemetry = *telemetry_ptr;
-    Ok(telemetry_val)
+/// How many bytes are readable from the start of `view`; 0 if it can't be queried.
+unsafe fn mapped_len(view: MEMORY_MAPPED_VIEW_ADDRESS) -> usize {
+    let mut info = MEMORY_BASIC_INFORMATION::default();
+    let written = VirtualQuery(
+        Some(view.Value as *const _),
+        &mut info,
+        std::mem::size_of::<MEMORY_BASIC_INFORMATION>(),
+    );
+    if written == 0 {
+        return 0;
+    }
+    let offset = view.Value as usize - info.BaseAddress as usize;
+    info.RegionSize.saturating_sub(offset)
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct MPData {
     pub curr_map: String,
     pub is_local_player: bool,
+    /// The local player's name, even while spectating someone else.
     pub player_name: String,
-    pub race_state: u32,
+    pub race_state: RaceState,
+    pub spectator_mode: SpectatorMode,
 }
 
 impl Default for MPData {
@@ -78,17 +179,30 @@ impl Default for MPData {
             curr_map: String::new(),
             is_local_player: false,
             player_name: String::new(),
-            race_state: 0,
+            race_state: RaceState::BeforeStart,
+            spectator_mode: SpectatorMode::Park,
         }
     }
 }
 
-pub static MP_DATA_UDPATE: Mutex<usize> = Mutex::new(0);
-lazy_static! {
-    pub static ref MP_CTX_LAST: Mutex<MPData> = Mutex::new(Default::default());
+/// How long to wait between attempts to open the telemetry mapping while the game isn't running.
+const WAIT_FOR_GAME_POLL: Duration = Duration::from_millis(1000);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TelemetryStatus {
+    /// The telemetry mapping doesn't exist (yet), i.e. the game isn't running.
+    WaitingForGame,
+    Running,
+    /// The mapping exists but `update_number` hasn't changed for a while (game frozen or minimized).
+    Stalled,
 }
 
-pub fn run_mp_telemetry_loop(mumble: &Arc<RwLock<io::Result<MumbleLink>>>, to_gui: &Sender<ToGUI>) -> Result<(), SendError<ToGUI>> {
+pub fn run_mp_telemetry_loop(
+    mumble: &SharedMumble,
+    state: &BridgeState,
+    to_gui: &Sender<ToGUI>,
+    stop: &AtomicBool,
+) -> Result<(), SendError<ToGUI>> {
     let mut last_ctx_update = Instant::now();
     let mut last_update_nb_change = Instant::now();
     let mut last_update_nb = 0;
@@ -96,27 +210,82 @@ pub fn run_mp_telemetry_loop(mumble: &Arc<RwLock<io::Result<MumbleLink>>>, to_gu
     let mut no_obj_frames = 0;
     let mut no_updates = false;
     let mut obj_updated;
-    loop {
+    let mut race_events = RaceEventDetector::default();
+    let mut last_discontinuity_count = 0;
+    let mut plugin_had_ctx = false;
+    // telemetry shows the spectated player's name while spectating
+    let mut local_player_name = String::new();
+    let mut status = TelemetryStatus::WaitingForGame;
+    to_gui.send(ToGUI::TelemetryStatus(status))?;
+    *state.telemetry_ctx() = MPData::default();
+    while !stop.load(Ordering::SeqCst) {
         thread::sleep(Duration::from_millis(10));
         let telemetry = match read_telemetry() {
-            Ok(t) => t,
+            Ok(t) => TelemetryFrame::from(&t),
+            Err(ReadTelemetryError::Torn) => {
+                state.update_stats(|s| s.torn_reads += 1);
+                continue;
+            }
             Err(e) => {
-                log::error!("Failed to read telemetry: {}", e);
-                return Ok(());
+                if status != TelemetryStatus::WaitingForGame {
+                    log::warn!("Lost Trackmania telemetry ({}), waiting for the game", e);
+                    // the game closed mid-session: park the player and forget the old session
+                    if let Ok(mumble) = mumble.lock().unwrap().as_mut() {
+                        // cleared first, since the context only reaches Mumble with an update
+                        if !plugin_owns_context(state) {
+                            mumble.set_context(b"");
+                            state.clear_context();
+                        }
+                        if accept_sample(state, SampleOrigin::Telemetry, false) {
+                            update_positions(
+                                mumble,
+                                state,
+                                PositionSample::new(position_near_zero(), position_near_zero()).with_teleport(true),
+                            );
+                        }
+                    }
+                    *state.telemetry_ctx() = MPData::default();
+                    race_events = RaceEventDetector::default();
+                    last_update_nb = 0;
+                    no_obj_frames = 0;
+                    status = TelemetryStatus::WaitingForGame;
+                    to_gui.send(ToGUI::TelemetryStatus(status))?;
+                }
+                sleep_unless_stopped(WAIT_FOR_GAME_POLL, stop);
+                continue;
             }
         };
+        if status == TelemetryStatus::WaitingForGame {
+            log::info!("Trackmania telemetry found");
+            last_update_nb_change = Instant::now();
+            no_updates = false;
+            status = TelemetryStatus::Running;
+            to_gui.send(ToGUI::TelemetryStatus(status))?;
+        }
+
+        export_frame(state, &telemetry);
 
         // Update the MumbleLink data
-        let mut mumble_w = mumble.write().unwrap();
-        let mumble = mumble_w.as_mut().unwrap();
+        let mut mumble_w = mumble.lock().unwrap();
+        let Ok(mumble) = mumble_w.as_mut() else {
+            continue;
+        };
 
-        // Send the telemetry data to the GUI
-        to_gui.send(ToGUI::Telemetry(telemetry)).unwrap();
+        state.publish_telemetry(telemetry.clone());
+
+        for event in race_events.update(&telemetry) {
+            state.publish_race_event(event);
+        }
 
         if last_update_nb != telemetry.update_number {
             last_update_nb = telemetry.update_number;
+            state.count_telemetry_frame();
             last_update_nb_change = Instant::now();
             no_updates = false;
+            if status != TelemetryStatus::Running {
+                status = TelemetryStatus::Running;
+                to_gui.send(ToGUI::TelemetryStatus(status))?;
+            }
             obj_updated = last_obj_ts != telemetry.object.timestamp;
             last_obj_ts = telemetry.object.timestamp;
             if !obj_updated {
@@ -126,36 +295,55 @@ pub fn run_mp_telemetry_loop(mumble: &Arc<RwLock<io::Result<MumbleLink>>>, to_gu
             }
         } else if (Instant::now() - last_update_nb_change).as_secs_f32() > 2.0 && !no_updates {
             no_updates = true;
+            status = TelemetryStatus::Stalled;
+            to_gui.send(ToGUI::TelemetryStatus(status))?;
             // log::warn!("No telemetry update for 2 seconds. Exiting MP telemetry loop.");
             // break;
         }
 
 
 
+        if telemetry.player.is_local_player {
+            local_player_name.clone_from(&telemetry.player.user_name);
+        }
+        let spectator_cfg = get_config().spectator;
         let curr_ctx = MPData {
-            // curr_map: String::from_utf8_lossy(&telemetry.game.map_id).to_string(),
-            // read cstr
-            curr_map: String::from_utf8_lossy(&telemetry.game.map_id.as_cstr_vec()).to_string(),
-            is_local_player: telemetry.player.is_local_player > 0,
-            player_name: String::from_utf8_lossy(&telemetry.player.user_name.as_cstr_vec()).to_string(),
+            curr_map: telemetry.game.map_id.clone(),
+            is_local_player: telemetry.player.is_local_player,
+            player_name: local_player_name.clone(),
             race_state: telemetry.race.state,
+            spectator_mode: spectator_cfg.mode,
         };
 
-        // what has changed since last time
-        let mut mp_data_update = MP_DATA_UDPATE.lock().unwrap();
-        let mut mp_ctx_last = MP_CTX_LAST.lock().unwrap();
-
-        let update_ctx = &curr_ctx != mp_ctx_last.deref()
-            || (Instant::now() - last_ctx_update).as_secs_f32() > 5.0;
+        // in hybrid mode the plugin sets the context while connected; take over again when it leaves
+        let plugin_ctx = plugin_owns_context(state);
+        let update_ctx = curr_ctx != *state.telemetry_ctx()
+            || (Instant::now() - last_ctx_update).as_secs_f32() > 5.0
+            || (plugin_had_ctx && !plugin_ctx);
+        plugin_had_ctx = plugin_ctx;
 
-        if update_ctx {
-            *mp_data_update += 1;
+        if update_ctx && !plugin_ctx {
             let team_str = "All".to_string();
-            let identity: String = format!("{}|{}|{}", &curr_ctx.player_name, &curr_ctx.player_name, *mp_data_update);
-            let context: String = format!("TM|{}|{}", &obfs_uid_or_svr_login(&curr_ctx.curr_map), &team_str);
+            let identity: String = format!(
+                "{}|{}|{}",
+                &curr_ctx.player_name,
+                &curr_ctx.player_name,
+                state.next_identity_nonce()
+            );
+            let mut context: String = format!("TM|{}|{}", &obfs_uid_or_svr_login(&curr_ctx.curr_map), &team_str);
+            // spectators are only told apart by context; the identity stays the player's own
+            if !curr_ctx.is_local_player {
+                context.push_str(spectator_context_suffix(curr_ctx.spectator_mode));
+            }
             mumble.set_identity(identity.as_str());
             mumble.set_context(context.as_bytes());
-            *LAST_CONTEXT.lock().unwrap() = context;
+            state.update_link(|l| {
+                l.player_name.clone_from(&curr_ctx.player_name);
+                l.player_login.clone_from(&curr_ctx.player_name);
+                l.server_login.clone_from(&curr_ctx.curr_map);
+                l.server_team.clone_from(&team_str);
+            });
+            state.set_context(context, identity);
             if let Err(e) = to_gui.send(FromTM::PlayerDetails(curr_ctx.player_name.clone(), curr_ctx.player_name.clone()).into()) {
                 log::error!("Failed to send ctx/id to GUI: {:?}", e);
                 break;
@@ -167,27 +355,50 @@ pub fn run_mp_telemetry_loop(mumble: &Arc<RwLock<io::Result<MumbleLink>>>, to_gu
             last_ctx_update = Instant::now();
         }
 
-        let unspawned = !curr_ctx.is_local_player
-            || no_updates
-            || no_obj_frames > 10;
+        observe(state, GameStatus::from(&telemetry));
+        let park = if !state.proximity_enabled() {
+            Some(ParkReason::Disabled)
+        } else if no_updates {
+            Some(ParkReason::Stalled)
+        } else if no_obj_frames > 10 {
+            Some(ParkReason::NotSpawned)
+        } else {
+            get_config().park.evaluate(&GameStatus::from(&telemetry), spectator_cfg.mode)
+        };
 
         let mut player: Position = get_player_data_from_telemetry(&telemetry);
-        if unspawned || no_updates {
+        let mut camera: Position = estimate_camera(&telemetry, &get_config().camera);
+        let velocity: Vector3<f32> = telemetry.object.velocity.into();
+        let mut sample = PositionSample::new(player, camera)
+            .with_velocity((velocity * MUMBLE_SCALE).into())
+            .with_teleport(telemetry.object.discontinuity_count != last_discontinuity_count);
+        last_discontinuity_count = telemetry.object.discontinuity_count;
+        if !telemetry.player.is_local_player {
+            // spectators are heard from, and listen at, the same spot
+            let at = match spectator_cfg.listen_at {
+                SpectatorListenAt::Car => player,
+                SpectatorListenAt::Camera => camera,
+            };
+            player = at;
+            camera = at;
+            sample.p = at;
+            sample.c = at;
+        }
+        if park.is_some() {
             player = position_near_zero();
+            camera = position_near_zero();
+            sample = PositionSample::new(player, camera).with_teleport(true);
         }
-        // let camera: Position = get_camera_data_from_telemetry(&telemetry);
-        let p = player.into();
-        let c = player.into();
-        mumble.update(player, player);
-        if let Err(e) = to_gui.send(FromTM::Positions {p, c}.into()) {
-            log::error!("Failed to send p & c to GUI: {:?}", e);
-            break;
+        if accept_sample(state, SampleOrigin::Telemetry, park.is_none()) {
+            report_park_status(state, park, to_gui);
+            update_positions(mumble, state, sample);
+            state.record_positions(sample.p.into(), sample.c.into());
         }
 
 
-        *mp_ctx_last = curr_ctx;
+        *state.telemetry_ctx() = curr_ctx;
     }
-    log::warn!("MP telemetry loop ended.");
+    log::info!("MP telemetry loop ended.");
     Ok(())
 }
 
@@ -201,8 +412,8 @@ pub fn position_near_zero() -> Position {
 }
 
 
-fn get_player_data_from_telemetry(telemetry: &STelemetry) -> Position {
-    let position: Vector3<_> = telemetry.object.translation.into();
+fn get_player_data_from_telemetry(telemetry: &TelemetryFrame) -> Position {
+    let position: Vector3<_> = telemetry.object.position.into();
     let position = position * MUMBLE_SCALE;
     let rot_q: Quaternion<_> = telemetry.object.rotation.into();
     let dir = rot_q * cgmath::Vector3::unit_z();
@@ -125,16 +125,118 @@ struct STelemetry {
 
 */
 
-use std::fmt::{Debug, Formatter, self};
+use std::fmt::{Debug, Display, Formatter, self};
 
+use bitflags::bitflags;
 use cgmath::Vector3;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 
 #[allow(unused)]
 const MP_T_VERSION: u32 = 3;
+const MP_T_MAGIC: &[u8] = b"ManiaPlanet_Telemetry";
+
+/// `EGameState`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GameState {
+    Starting,
+    Menus,
+    Running,
+    Paused,
+    Unknown(u32),
+}
+
+impl From<u32> for GameState {
+    fn from(v: u32) -> Self {
+        match v {
+            0 => GameState::Starting,
+            1 => GameState::Menus,
+            2 => GameState::Running,
+            3 => GameState::Paused,
+            v => GameState::Unknown(v),
+        }
+    }
+}
+
+impl Display for GameState {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            GameState::Unknown(v) => write!(f, "Unknown({})", v),
+            s => write!(f, "{:?}", s),
+        }
+    }
+}
+
+/// `ERaceState`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RaceState {
+    BeforeStart,
+    Running,
+    Finished,
+    Unknown(u32),
+}
+
+impl From<u32> for RaceState {
+    fn from(v: u32) -> Self {
+        match v {
+            0 => RaceState::BeforeStart,
+            1 => RaceState::Running,
+            2 => RaceState::Finished,
+            v => RaceState::Unknown(v),
+        }
+    }
+}
+
+impl Display for RaceState {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            RaceState::Unknown(v) => write!(f, "Unknown({})", v),
+            s => write!(f, "{:?}", s),
+        }
+    }
+}
+
+bitflags! {
+    /// `SVehicleState.Handicap`: `[reserved..] [NoGrip] [NoSteering] [NoBrakes] [EngineForcedOn] [EngineForcedOff]`
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+    pub struct Handicap: u32 {
+        const ENGINE_FORCED_OFF = 1 << 0;
+        const ENGINE_FORCED_ON = 1 << 1;
+        const NO_BRAKES = 1 << 2;
+        const NO_STEERING = 1 << 3;
+        const NO_GRIP = 1 << 4;
+    }
+}
+
+fn cstr_string(s: &impl AsCStrSlice) -> String {
+    String::from_utf8_lossy(&s.as_cstr_vec()).to_string()
+}
+
+/// Serde for fixed size C strings: written as strings, read back NUL padded (and truncated to fit).
+mod cstr {
+    use super::*;
+
+    pub fn serialize<S: Serializer, const N: usize>(v: &[u8; N], s: S) -> Result<S::Ok, S::Error> {
+        let len = v.iter().position(|c| *c == 0).unwrap_or(N);
+        s.serialize_str(&String::from_utf8_lossy(&v[..len]))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>, const N: usize>(d: D) -> Result<[u8; N], D::Error> {
+        let s = String::deserialize(d)?;
+        let mut buf = [0u8; N];
+        let n = s.len().min(N - 1);
+        buf[..n].copy_from_slice(&s.as_bytes()[..n]);
+        Ok(buf)
+    }
+}
+
+/// `__future__` padding isn't serialized.
+fn zeroed<const N: usize>() -> [u8; N] {
+    [0; N]
+}
 
 #[repr(C)]
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct Vec3 {
     pub x: f32,
     pub y: f32,
@@ -160,7 +262,7 @@ impl From<Vec3> for Vector3<f32> {
 }
 
 #[repr(C)]
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct Quat {
     pub w: f32,
     pub x: f32,
@@ -181,13 +283,20 @@ impl From<Quat> for cgmath::Quaternion<f32> {
 }
 
 #[repr(C)]
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Serialize, Deserialize)]
 pub struct SHeader {
+    #[serde(with = "cstr")]
     pub magic: [u8; 32],
     pub version: u32,
     pub size: u32,
 }
 
+impl SHeader {
+    pub fn has_valid_magic(&self) -> bool {
+        self.magic.as_cstr_vec() == MP_T_MAGIC
+    }
+}
+
 impl Debug for SHeader {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
         write!(f, "SHeader {{ magic: {:?},
@@ -198,27 +307,50 @@ impl Debug for SHeader {
 }
 
 #[repr(C)]
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Serialize, Deserialize)]
 pub struct SGameState {
     pub state: u32,
+    #[serde(with = "cstr")]
     pub gameplay_variant: [u8; 64],
+    #[serde(with = "cstr")]
     pub map_id: [u8; 64],
+    #[serde(with = "cstr")]
     pub map_name: [u8; 256],
+    #[serde(skip, default = "zeroed")]
     pub future: [u8; 128],
 }
 
+impl SGameState {
+    pub fn game_state(&self) -> GameState {
+        self.state.into()
+    }
+
+    pub fn gameplay_variant(&self) -> String {
+        cstr_string(&self.gameplay_variant)
+    }
+
+    pub fn map_id(&self) -> String {
+        cstr_string(&self.map_id)
+    }
+
+    pub fn map_name(&self) -> String {
+        cstr_string(&self.map_name)
+    }
+}
+
 impl Debug for SGameState {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
         write!(f, "SGameState {{ state: {},
         gameplay_variant: {:?},
         map_id: {:?},
         map_name: {:?} }}",
-               self.state, String::from_utf8_lossy(&self.gameplay_variant.as_cstr_vec()), String::from_utf8_lossy(&self.map_id.as_cstr_vec()), String::from_utf8_lossy(&self.map_name.as_cstr_vec()))
+               self.game_state(), self.gameplay_variant(), self.map_id(), self.map_name())
     }
 }
 
 #[repr(C)]
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Serialize, Deserialize)]
+#[serde(into = "RaceStateSerde", from = "RaceStateSerde")]
 pub struct SRaceState {
     pub state: u32,
     pub time: u32,
@@ -233,6 +365,68 @@ pub struct SRaceState {
 }
 
 
+impl SRaceState {
+    pub fn race_state(&self) -> RaceState {
+        self.state.into()
+    }
+
+    /// The times of the checkpoints passed so far.
+    pub fn checkpoint_times(&self) -> &[u32] {
+        let n = (self.nb_checkpoints as usize).min(self.checkpoint_times.len());
+        &self.checkpoint_times[..n]
+    }
+}
+
+/// `SRaceState` as serialized: checkpoint times are trimmed to `nb_checkpoints`.
+#[derive(Serialize, Deserialize)]
+struct RaceStateSerde {
+    state: u32,
+    time: u32,
+    nb_respawns: u32,
+    nb_checkpoints: u32,
+    checkpoint_times: Vec<u32>,
+    nb_checkpoints_per_lap: u32,
+    nb_laps_per_race: u32,
+    timestamp: u32,
+    start_timestamp: u32,
+}
+
+impl From<SRaceState> for RaceStateSerde {
+    fn from(r: SRaceState) -> Self {
+        RaceStateSerde {
+            state: r.state,
+            time: r.time,
+            nb_respawns: r.nb_respawns,
+            nb_checkpoints: r.nb_checkpoints,
+            checkpoint_times: r.checkpoint_times().to_vec(),
+            nb_checkpoints_per_lap: r.nb_checkpoints_per_lap,
+            nb_laps_per_race: r.nb_laps_per_race,
+            timestamp: r.timestamp,
+            start_timestamp: r.start_timestamp,
+        }
+    }
+}
+
+impl From<RaceStateSerde> for SRaceState {
+    fn from(r: RaceStateSerde) -> Self {
+        let mut checkpoint_times = [0; 125];
+        let n = r.checkpoint_times.len().min(checkpoint_times.len());
+        checkpoint_times[..n].copy_from_slice(&r.checkpoint_times[..n]);
+        SRaceState {
+            state: r.state,
+            time: r.time,
+            nb_respawns: r.nb_respawns,
+            nb_checkpoints: r.nb_checkpoints,
+            checkpoint_times,
+            nb_checkpoints_per_lap: r.nb_checkpoints_per_lap,
+            nb_laps_per_race: r.nb_laps_per_race,
+            timestamp: r.timestamp,
+            start_timestamp: r.start_timestamp,
+            future: [0; 16],
+        }
+    }
+}
+
 impl Debug for SRaceState {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
         write!(f, "SRaceState {{ state: {},
@@ -244,13 +438,13 @@ impl Debug for SRaceState {
         nb_laps_per_race: {},
         timestamp: {},
         start_timestamp: {} }}",
-               self.state, self.time, self.nb_respawns, self.nb_checkpoints, &self.checkpoint_times[..self.nb_checkpoints as usize], self.nb_checkpoints_per_lap, self.nb_laps_per_race, self.timestamp, self.start_timestamp)
+               self.race_state(), self.time, self.nb_respawns, self.nb_checkpoints, self.checkpoint_times(), self.nb_checkpoints_per_lap, self.nb_laps_per_race, self.timestamp, self.start_timestamp)
     }
 }
 
 
 #[repr(C)]
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Serialize, Deserialize)]
 pub struct SObjectState {
     pub timestamp: u32,
     pub discontinuity_count: u32,
@@ -258,6 +452,7 @@ pub struct SObjectState {
     pub translation: Vec3,
     pub velocity: Vec3,
     pub latest_stable_ground_contact_time: u32,
+    #[serde(skip, default = "zeroed")]
     pub future: [u8; 32],
 }
 
@@ -276,7 +471,7 @@ impl Debug for SObjectState {
 
 
 #[repr(C)]
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Serialize, Deserialize)]
 pub struct SVehicleState {
     pub timestamp: u32,
     pub input_steer: f32,
@@ -302,9 +497,61 @@ pub struct SVehicleState {
     pub is_on_ice: u32,
     pub handicap: u32,
     pub boost_ratio: f32,
+    #[serde(skip, default = "zeroed")]
     pub future: [u8; 20],
 }
 
+impl SVehicleState {
+    pub fn input_is_braking(&self) -> bool {
+        self.input_is_braking != 0
+    }
+
+    pub fn input_is_horn(&self) -> bool {
+        self.input_is_horn != 0
+    }
+
+    pub fn engine_freewheeling(&self) -> bool {
+        self.engine_freewheeling != 0
+    }
+
+    pub fn wheels_is_ground_contact(&self) -> [bool; 4] {
+        self.wheels_is_ground_contact.map(|w| w != 0)
+    }
+
+    pub fn wheels_is_slipping(&self) -> [bool; 4] {
+        self.wheels_is_slipping.map(|w| w != 0)
+    }
+
+    pub fn is_in_water(&self) -> bool {
+        self.is_in_water != 0
+    }
+
+    pub fn is_sparkling(&self) -> bool {
+        self.is_sparkling != 0
+    }
+
+    pub fn is_light_trails(&self) -> bool {
+        self.is_light_trails != 0
+    }
+
+    pub fn is_lights_on(&self) -> bool {
+        self.is_lights_on != 0
+    }
+
+    pub fn is_flying(&self) -> bool {
+        self.is_flying != 0
+    }
+
+    pub fn is_on_ice(&self) -> bool {
+        self.is_on_ice != 0
+    }
+
+    /// Unknown (reserved) bits are kept.
+    pub fn handicap(&self) -> Handicap {
+        Handicap::from_bits_retain(self.handicap)
+    }
+}
+
 impl Debug for SVehicleState {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
         write!(f, "SVehicleState {{ timestamp: {},
@@ -329,19 +576,20 @@ impl Debug for SVehicleState {
         is_lights_on: {},
         is_flying: {},
         is_on_ice: {},
-        handicap: {},
+        handicap: {:?},
         boost_ratio: {} }}",
-               self.timestamp, self.input_steer, self.input_gas_pedal, self.input_is_braking, self.input_is_horn, self.engine_rpm, self.engine_cur_gear, self.engine_turbo_ratio, self.engine_freewheeling, &self.wheels_is_ground_contact[..], &self.wheels_is_slipping[..],
-               self.wheels_damper_len[0], self.wheels_damper_len[1], self.wheels_damper_len[2], self.wheels_damper_len[3], self.wheels_damper_range_min, self.wheels_damper_range_max, self.rumble_intensity, self.speed_meter, self.is_in_water, self.is_sparkling, self.is_light_trails, self.is_lights_on, self.is_flying, self.is_on_ice, self.handicap, self.boost_ratio)
+               self.timestamp, self.input_steer, self.input_gas_pedal, self.input_is_braking(), self.input_is_horn(), self.engine_rpm, self.engine_cur_gear, self.engine_turbo_ratio, self.engine_freewheeling(), self.wheels_is_ground_contact(), self.wheels_is_slipping(),
+               self.wheels_damper_len[0], self.wheels_damper_len[1], self.wheels_damper_len[2], self.wheels_damper_len[3], self.wheels_damper_range_min, self.wheels_damper_range_max, self.rumble_intensity, self.speed_meter, self.is_in_water(), self.is_sparkling(), self.is_light_trails(), self.is_lights_on(), self.is_flying(), self.is_on_ice(), self.handicap(), self.boost_ratio)
     }
 }
 
 #[repr(C)]
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Serialize, Deserialize)]
 pub struct SDeviceState {
     pub euler: Vec3,
     pub centered_yaw: f32,
     pub centered_altitude: f32,
+    #[serde(skip, default = "zeroed")]
     pub future: [u8; 32],
 }
 
@@ -355,16 +603,38 @@ impl Debug for SDeviceState {
 }
 
 #[repr(C)]
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Serialize, Deserialize)]
 pub struct SPlayerState {
     pub is_local_player: u32,
+    #[serde(with = "cstr")]
     pub trigram: [u8; 4],
+    #[serde(with = "cstr")]
     pub dossard_number: [u8; 4],
     pub hue: f32,
+    #[serde(with = "cstr")]
     pub user_name: [u8; 256],
+    #[serde(skip, default = "zeroed")]
     pub future: [u8; 28],
 }
 
+impl SPlayerState {
+    pub fn is_local_player(&self) -> bool {
+        self.is_local_player != 0
+    }
+
+    pub fn trigram(&self) -> String {
+        cstr_string(&self.trigram)
+    }
+
+    pub fn dossard_number(&self) -> String {
+        cstr_string(&self.dossard_number)
+    }
+
+    pub fn user_name(&self) -> String {
+        cstr_string(&self.user_name)
+    }
+}
+
 impl Debug for SPlayerState {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
         write!(f, "SPlayerState {{ is_local_player: {},
@@ -372,14 +642,14 @@ impl Debug for SPlayerState {
         dossard_number: {:?},
         hue: {},
         user_name: {:?} }}",
-               self.is_local_player, String::from_utf8_lossy(&self.trigram.as_cstr_vec()), String::from_utf8_lossy(&self.dossard_number.as_cstr_vec()), self.hue, String::from_utf8_lossy(&self.user_name.as_cstr_vec()))
+               self.is_local_player(), self.trigram(), self.dossard_number(), self.hue, self.user_name())
     }
 }
 
 
 
 #[repr(C)]
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub struct STelemetry {
     pub header: SHeader,
     pub update_number: u32,
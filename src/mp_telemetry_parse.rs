@@ -0,0 +1,412 @@
+//! Safe parsing of the `ManiaPlanet_Telemetry` shared memory block into `STelemetry`.
+//!
+//! Every field is read explicitly as little-endian from a length-checked byte slice, so a short or
+//! garbage mapping is an error rather than undefined behaviour. The `#[repr(C)]` structs are still
+//! kept in sync with the C header via the compile-time layout assertions below.
+
+use std::fmt::{self, Display, Formatter};
+use std::io::{self, Cursor, Read};
+use std::mem::{offset_of, size_of};
+
+use byteorder::{LittleEndian, ReadBytesExt};
+
+use crate::mp_telemetry_data::{
+    Quat, SDeviceState, SGameState, SHeader, SObjectState, SPlayerState, SRaceState, STelemetry,
+    SVehicleState, Vec3,
+};
+
+type LE = LittleEndian;
+
+/// `sizeof(STelemetry)` for `ECurVersion = 3`.
+pub const STELEMETRY_SIZE: usize = 1696;
+
+// Layout of the C header (all fields are 4-byte aligned, so there's no padding).
+const _: () = {
+    assert!(size_of::<Vec3>() == 12);
+    assert!(size_of::<Quat>() == 16);
+    assert!(size_of::<SHeader>() == 40);
+    assert!(size_of::<SGameState>() == 516);
+    assert!(size_of::<SRaceState>() == 548);
+    assert!(size_of::<SObjectState>() == 84);
+    assert!(size_of::<SVehicleState>() == 152);
+    assert!(size_of::<SDeviceState>() == 52);
+    assert!(size_of::<SPlayerState>() == 300);
+    assert!(size_of::<STelemetry>() == STELEMETRY_SIZE);
+
+    assert!(offset_of!(STelemetry, header) == 0);
+    assert!(offset_of!(STelemetry, update_number) == 40);
+    assert!(offset_of!(STelemetry, game) == 44);
+    assert!(offset_of!(STelemetry, race) == 560);
+    assert!(offset_of!(STelemetry, object) == 1108);
+    assert!(offset_of!(STelemetry, vehicle) == 1192);
+    assert!(offset_of!(STelemetry, device) == 1344);
+    assert!(offset_of!(STelemetry, player) == 1396);
+
+    assert!(offset_of!(SGameState, map_id) == 68);
+    assert!(offset_of!(SGameState, map_name) == 132);
+    assert!(offset_of!(SRaceState, checkpoint_times) == 16);
+    assert!(offset_of!(SRaceState, nb_checkpoints_per_lap) == 516);
+    assert!(offset_of!(SObjectState, rotation) == 8);
+    assert!(offset_of!(SObjectState, translation) == 24);
+    assert!(offset_of!(SObjectState, velocity) == 36);
+    assert!(offset_of!(SVehicleState, engine_rpm) == 20);
+    assert!(offset_of!(SVehicleState, wheels_is_ground_contact) == 36);
+    assert!(offset_of!(SVehicleState, speed_meter) == 96);
+    assert!(offset_of!(SVehicleState, handicap) == 124);
+    assert!(offset_of!(SPlayerState, hue) == 12);
+    assert!(offset_of!(SPlayerState, user_name) == 16);
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TelemetryParseError {
+    TooShort { len: usize, expected: usize },
+    BadMagic,
+    /// The header claims a size smaller than the version 3 layout.
+    BadSize(u32),
+}
+
+impl Display for TelemetryParseError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            TelemetryParseError::TooShort { len, expected } => {
+                write!(f, "telemetry data too short: {} bytes, expected {}", len, expected)
+            }
+            TelemetryParseError::BadMagic => write!(f, "telemetry header has an invalid magic"),
+            TelemetryParseError::BadSize(size) => {
+                write!(f, "telemetry header size {} is smaller than {}", size, STELEMETRY_SIZE)
+            }
+        }
+    }
+}
+
+impl std::error::Error for TelemetryParseError {}
+
+impl STelemetry {
+    /// Parse a telemetry block. Trailing bytes (from newer, larger versions) are ignored.
+    pub fn from_bytes(data: &[u8]) -> Result<STelemetry, TelemetryParseError> {
+        if data.len() < STELEMETRY_SIZE {
+            return Err(TelemetryParseError::TooShort {
+                len: data.len(),
+                expected: STELEMETRY_SIZE,
+            });
+        }
+        let mut r = Cursor::new(&data[..STELEMETRY_SIZE]);
+        // the length is checked above, so none of the reads below can run out of data
+        let telemetry = read_telemetry(&mut r).expect("length checked");
+        if !telemetry.header.has_valid_magic() {
+            return Err(TelemetryParseError::BadMagic);
+        }
+        if (telemetry.header.size as usize) < STELEMETRY_SIZE {
+            return Err(TelemetryParseError::BadSize(telemetry.header.size));
+        }
+        Ok(telemetry)
+    }
+}
+
+type R<'a> = Cursor<&'a [u8]>;
+
+fn read_telemetry(r: &mut R) -> io::Result<STelemetry> {
+    Ok(STelemetry {
+        header: read_header(r)?,
+        update_number: r.read_u32::<LE>()?,
+        game: read_game(r)?,
+        race: read_race(r)?,
+        object: read_object(r)?,
+        vehicle: read_vehicle(r)?,
+        device: read_device(r)?,
+        player: read_player(r)?,
+    })
+}
+
+fn read_bytes<const N: usize>(r: &mut R) -> io::Result<[u8; N]> {
+    let mut buf = [0u8; N];
+    r.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+fn read_u32s<const N: usize>(r: &mut R) -> io::Result<[u32; N]> {
+    let mut buf = [0u32; N];
+    r.read_u32_into::<LE>(&mut buf)?;
+    Ok(buf)
+}
+
+fn read_f32s<const N: usize>(r: &mut R) -> io::Result<[f32; N]> {
+    let mut buf = [0f32; N];
+    r.read_f32_into::<LE>(&mut buf)?;
+    Ok(buf)
+}
+
+fn read_vec3(r: &mut R) -> io::Result<Vec3> {
+    let [x, y, z] = read_f32s(r)?;
+    Ok(Vec3 { x, y, z })
+}
+
+fn read_quat(r: &mut R) -> io::Result<Quat> {
+    let [w, x, y, z] = read_f32s(r)?;
+    Ok(Quat { w, x, y, z })
+}
+
+fn read_header(r: &mut R) -> io::Result<SHeader> {
+    Ok(SHeader {
+        magic: read_bytes(r)?,
+        version: r.read_u32::<LE>()?,
+        size: r.read_u32::<LE>()?,
+    })
+}
+
+fn read_game(r: &mut R) -> io::Result<SGameState> {
+    Ok(SGameState {
+        state: r.read_u32::<LE>()?,
+        gameplay_variant: read_bytes(r)?,
+        map_id: read_bytes(r)?,
+        map_name: read_bytes(r)?,
+        future: read_bytes(r)?,
+    })
+}
+
+fn read_race(r: &mut R) -> io::Result<SRaceState> {
+    Ok(SRaceState {
+        state: r.read_u32::<LE>()?,
+        time: r.read_u32::<LE>()?,
+        nb_respawns: r.read_u32::<LE>()?,
+        nb_checkpoints: r.read_u32::<LE>()?,
+        checkpoint_times: read_u32s(r)?,
+        nb_checkpoints_per_lap: r.read_u32::<LE>()?,
+        nb_laps_per_race: r.read_u32::<LE>()?,
+        timestamp: r.read_u32::<LE>()?,
+        start_timestamp: r.read_u32::<LE>()?,
+        future: read_bytes(r)?,
+    })
+}
+
+fn read_object(r: &mut R) -> io::Result<SObjectState> {
+    Ok(SObjectState {
+        timestamp: r.read_u32::<LE>()?,
+        discontinuity_count: r.read_u32::<LE>()?,
+        rotation: read_quat(r)?,
+        translation: read_vec3(r)?,
+        velocity: read_vec3(r)?,
+        latest_stable_ground_contact_time: r.read_u32::<LE>()?,
+        future: read_bytes(r)?,
+    })
+}
+
+fn read_vehicle(r: &mut R) -> io::Result<SVehicleState> {
+    Ok(SVehicleState {
+        timestamp: r.read_u32::<LE>()?,
+        input_steer: r.read_f32::<LE>()?,
+        input_gas_pedal: r.read_f32::<LE>()?,
+        input_is_braking: r.read_u32::<LE>()?,
+        input_is_horn: r.read_u32::<LE>()?,
+        engine_rpm: r.read_f32::<LE>()?,
+        engine_cur_gear: r.read_i32::<LE>()?,
+        engine_turbo_ratio: r.read_f32::<LE>()?,
+        engine_freewheeling: r.read_u32::<LE>()?,
+        wheels_is_ground_contact: read_u32s(r)?,
+        wheels_is_slipping: read_u32s(r)?,
+        wheels_damper_len: read_f32s(r)?,
+        wheels_damper_range_min: r.read_f32::<LE>()?,
+        wheels_damper_range_max: r.read_f32::<LE>()?,
+        rumble_intensity: r.read_f32::<LE>()?,
+        speed_meter: r.read_u32::<LE>()?,
+        is_in_water: r.read_u32::<LE>()?,
+        is_sparkling: r.read_u32::<LE>()?,
+        is_light_trails: r.read_u32::<LE>()?,
+        is_lights_on: r.read_u32::<LE>()?,
+        is_flying: r.read_u32::<LE>()?,
+        is_on_ice: r.read_u32::<LE>()?,
+        handicap: r.read_u32::<LE>()?,
+        boost_ratio: r.read_f32::<LE>()?,
+        future: read_bytes(r)?,
+    })
+}
+
+fn read_device(r: &mut R) -> io::Result<SDeviceState> {
+    Ok(SDeviceState {
+        euler: read_vec3(r)?,
+        centered_yaw: r.read_f32::<LE>()?,
+        centered_altitude: r.read_f32::<LE>()?,
+        future: read_bytes(r)?,
+    })
+}
+
+fn read_player(r: &mut R) -> io::Result<SPlayerState> {
+    Ok(SPlayerState {
+        is_local_player: r.read_u32::<LE>()?,
+        trigram: read_bytes(r)?,
+        dossard_number: read_bytes(r)?,
+        hue: r.read_f32::<LE>()?,
+        user_name: read_bytes(r)?,
+        future: read_bytes(r)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mp_telemetry_data::{GameState, Handicap, RaceState};
+
+    /// Build a frame the way the game lays it out in memory, writing fields at their C offsets.
+    fn fixture_frame() -> Vec<u8> {
+        let mut buf = vec![0u8; STELEMETRY_SIZE];
+        let put = |buf: &mut Vec<u8>, off: usize, bytes: &[u8]| {
+            buf[off..off + bytes.len()].copy_from_slice(bytes);
+        };
+        let put_u32 = |buf: &mut Vec<u8>, off: usize, v: u32| put(buf, off, &v.to_le_bytes());
+        let put_f32 = |buf: &mut Vec<u8>, off: usize, v: f32| put(buf, off, &v.to_le_bytes());
+
+        // header
+        put(&mut buf, 0, b"ManiaPlanet_Telemetry");
+        put_u32(&mut buf, 32, 3);
+        put_u32(&mut buf, 36, STELEMETRY_SIZE as u32);
+        put_u32(&mut buf, 40, 12345);
+        // game
+        put_u32(&mut buf, 44, 2);
+        put(&mut buf, 48, b"CarSport");
+        put(&mut buf, 112, b"DqTfYhZ3Yb0oQeH1Hn4Vh1mGQ0b");
+        put(&mut buf, 176, b"Fall 2024 - 01");
+        // race
+        put_u32(&mut buf, 560, 1);
+        put_u32(&mut buf, 564, 15230);
+        put_u32(&mut buf, 568, 2);
+        put_u32(&mut buf, 572, 3);
+        put_u32(&mut buf, 576, 4100);
+        put_u32(&mut buf, 580, 9050);
+        put_u32(&mut buf, 584, 14900);
+        put_u32(&mut buf, 1076, 5);
+        put_u32(&mut buf, 1080, 1);
+        // object
+        put_u32(&mut buf, 1108, 777);
+        put_u32(&mut buf, 1112, 4);
+        put_f32(&mut buf, 1116, 1.0);
+        put_f32(&mut buf, 1132, 512.0);
+        put_f32(&mut buf, 1136, 24.5);
+        put_f32(&mut buf, 1140, -96.25);
+        put_f32(&mut buf, 1144, 10.0);
+        put_f32(&mut buf, 1152, 55.5);
+        // vehicle
+        put_f32(&mut buf, 1192 + 4, -0.5);
+        put_f32(&mut buf, 1192 + 20, 9876.0);
+        put(&mut buf, 1192 + 24, &(-1i32).to_le_bytes());
+        put_u32(&mut buf, 1192 + 36, 1);
+        put_u32(&mut buf, 1192 + 52, 1);
+        put_u32(&mut buf, 1192 + 96, 212);
+        put_u32(&mut buf, 1192 + 116, 1);
+        put_u32(&mut buf, 1192 + 124, 0b10100);
+        put_f32(&mut buf, 1192 + 128, 0.75);
+        // player
+        put_u32(&mut buf, 1396, 1);
+        put(&mut buf, 1400, b"XTV");
+        put(&mut buf, 1404, b"07");
+        put_f32(&mut buf, 1408, 0.33);
+        put(&mut buf, 1412, b"XertroV");
+        buf
+    }
+
+    #[test]
+    fn parses_fixture_frame() {
+        let t = STelemetry::from_bytes(&fixture_frame()).expect("fixture to parse");
+        assert_eq!(t.header.version, 3);
+        assert_eq!(t.update_number, 12345);
+        assert_eq!(t.game.game_state(), GameState::Running);
+        assert_eq!(t.game.gameplay_variant(), "CarSport");
+        assert_eq!(t.game.map_id(), "DqTfYhZ3Yb0oQeH1Hn4Vh1mGQ0b");
+        assert_eq!(t.game.map_name(), "Fall 2024 - 01");
+        assert_eq!(t.race.race_state(), RaceState::Running);
+        assert_eq!(t.race.nb_respawns, 2);
+        assert_eq!(t.race.checkpoint_times(), &[4100, 9050, 14900]);
+        assert_eq!(t.race.nb_checkpoints_per_lap, 5);
+        assert_eq!(t.object.discontinuity_count, 4);
+        assert_eq!(t.object.rotation.w, 1.0);
+        assert_eq!(<[f32; 3]>::from(t.object.translation), [512.0, 24.5, -96.25]);
+        assert_eq!(t.object.velocity.z, 55.5);
+        assert_eq!(t.vehicle.input_steer, -0.5);
+        assert_eq!(t.vehicle.engine_rpm, 9876.0);
+        assert_eq!(t.vehicle.engine_cur_gear, -1);
+        assert_eq!(t.vehicle.wheels_is_ground_contact(), [true, false, false, false]);
+        assert_eq!(t.vehicle.wheels_is_slipping(), [true, false, false, false]);
+        assert_eq!(t.vehicle.speed_meter, 212);
+        assert!(t.vehicle.is_flying());
+        assert_eq!(t.vehicle.handicap(), Handicap::NO_BRAKES | Handicap::NO_GRIP);
+        assert_eq!(t.vehicle.boost_ratio, 0.75);
+        assert!(t.player.is_local_player());
+        assert_eq!(t.player.trigram(), "XTV");
+        assert_eq!(t.player.dossard_number(), "07");
+        assert_eq!(t.player.user_name(), "XertroV");
+    }
+
+    #[test]
+    fn parser_matches_repr_c_layout() {
+        let frame = fixture_frame();
+        let parsed = STelemetry::from_bytes(&frame).unwrap();
+        // SAFETY: the frame is exactly size_of::<STelemetry>() bytes and every field is plain data.
+        let cast: STelemetry = unsafe { std::ptr::read_unaligned(frame.as_ptr().cast()) };
+        assert_eq!(format!("{:?}", parsed), format!("{:?}", cast));
+    }
+
+    #[test]
+    fn rejects_short_and_invalid_frames() {
+        let frame = fixture_frame();
+        assert_eq!(
+            STelemetry::from_bytes(&frame[..100]).unwrap_err(),
+            TelemetryParseError::TooShort {
+                len: 100,
+                expected: STELEMETRY_SIZE
+            }
+        );
+
+        let mut bad_magic = frame.clone();
+        bad_magic[0] = b'X';
+        assert_eq!(STelemetry::from_bytes(&bad_magic).unwrap_err(), TelemetryParseError::BadMagic);
+
+        let zeroed = vec![0u8; STELEMETRY_SIZE];
+        assert_eq!(STelemetry::from_bytes(&zeroed).unwrap_err(), TelemetryParseError::BadMagic);
+
+        let mut bad_size = frame.clone();
+        bad_size[36..40].copy_from_slice(&8u32.to_le_bytes());
+        assert_eq!(STelemetry::from_bytes(&bad_size).unwrap_err(), TelemetryParseError::BadSize(8));
+    }
+
+    #[test]
+    fn serde_round_trip() {
+        let t = STelemetry::from_bytes(&fixture_frame()).unwrap();
+        let v = serde_json::to_value(t).unwrap();
+        assert_eq!(v["header"]["magic"], "ManiaPlanet_Telemetry");
+        assert_eq!(v["game"]["map_name"], "Fall 2024 - 01");
+        assert_eq!(v["race"]["checkpoint_times"], serde_json::json!([4100, 9050, 14900]));
+        assert!(v["game"].get("future").is_none());
+
+        let back: STelemetry = serde_json::from_value(v).unwrap();
+        assert_eq!(format!("{:?}", back), format!("{:?}", t));
+    }
+
+    #[test]
+    fn ignores_trailing_bytes() {
+        let mut frame = fixture_frame();
+        frame.extend_from_slice(&[0xAA; 64]);
+        assert!(STelemetry::from_bytes(&frame).is_ok());
+    }
+
+    /// Snapshots saved from the game with `--dump-telemetry`: each `.bin` must parse to exactly
+    /// what its `.json` says.
+    #[test]
+    fn parses_captured_frames() {
+        let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/telemetry");
+        let mut checked = 0;
+        for entry in std::fs::read_dir(&dir).expect("testdata/telemetry to exist") {
+            let path = entry.unwrap().path();
+            if path.extension().is_none_or(|e| e != "bin") {
+                continue;
+            }
+            let bytes = std::fs::read(&path).unwrap();
+            // through `STelemetry`, so floats compare as the f32s they were written from
+            let expected: STelemetry =
+                serde_json::from_slice(&std::fs::read(path.with_extension("json")).unwrap()).unwrap();
+            let t = STelemetry::from_bytes(&bytes)
+                .unwrap_or_else(|e| panic!("{} didn't parse: {}", path.display(), e));
+            assert_eq!(format!("{:?}", t), format!("{:?}", expected), "{}", path.display());
+            checked += 1;
+        }
+        assert!(checked > 0, "no snapshots in {}", dir.display());
+    }
+}
@@ -0,0 +1,243 @@
+use std::fmt::{self, Display, Formatter};
+use std::sync::mpsc::Sender;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::app::ToGUI;
+use crate::bridge_state::BridgeState;
+use crate::config::get_config;
+use crate::maniaplanet_telemetry::read_telemetry;
+use crate::mp_telemetry_data::{GameState, RaceState};
+use crate::spectator::SpectatorMode;
+use crate::telemetry_frame::TelemetryFrame;
+
+/// Game state older than this is ignored by the rules.
+const OBSERVED_MAX_AGE: Duration = Duration::from_millis(500);
+/// How often the plugin source peeks at telemetry when nothing else is reading it.
+const PEEK_INTERVAL: Duration = Duration::from_millis(500);
+
+/// When to park the player (move them out of everyone's hearing range).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ParkRules {
+    pub in_menus: bool,
+    pub when_paused: bool,
+    pub after_finish: bool,
+}
+
+impl Default for ParkRules {
+    fn default() -> Self {
+        ParkRules {
+            in_menus: true,
+            when_paused: false,
+            after_finish: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParkReason {
+    /// Proximity chat was switched off (from the tray).
+    Disabled,
+    /// The car hasn't moved for a while (not spawned yet).
+    NotSpawned,
+    /// Telemetry stopped updating.
+    Stalled,
+    Menus,
+    Paused,
+    Finished,
+    Spectating,
+}
+
+impl Display for ParkReason {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            ParkReason::Disabled => write!(f, "proximity off"),
+            ParkReason::NotSpawned => write!(f, "not spawned"),
+            ParkReason::Stalled => write!(f, "telemetry stalled"),
+            ParkReason::Menus => write!(f, "in menus"),
+            ParkReason::Paused => write!(f, "paused"),
+            ParkReason::Finished => write!(f, "finished"),
+            ParkReason::Spectating => write!(f, "spectating"),
+        }
+    }
+}
+
+/// The parts of the game state the rules look at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GameStatus {
+    pub game: GameState,
+    pub race: RaceState,
+    pub spectating: bool,
+}
+
+impl From<&TelemetryFrame> for GameStatus {
+    fn from(t: &TelemetryFrame) -> Self {
+        GameStatus {
+            game: t.game.state,
+            race: t.race.state,
+            spectating: !t.player.is_local_player,
+        }
+    }
+}
+
+impl ParkRules {
+    /// Spectators are parked if `spectators` is `SpectatorMode::Park`.
+    pub fn evaluate(&self, status: &GameStatus, spectators: SpectatorMode) -> Option<ParkReason> {
+        match status.game {
+            GameState::Menus | GameState::Starting if self.in_menus => Some(ParkReason::Menus),
+            GameState::Paused if self.when_paused => Some(ParkReason::Paused),
+            _ if status.race == RaceState::Finished && self.after_finish => Some(ParkReason::Finished),
+            _ if status.spectating && spectators == SpectatorMode::Park => Some(ParkReason::Spectating),
+            _ => None,
+        }
+    }
+}
+
+/// Game state seen by the telemetry source, shared with the plugin source.
+#[derive(Default)]
+pub(crate) struct Observed {
+    status: Option<(GameStatus, Instant)>,
+    last_peek: Option<Instant>,
+    /// What the GUI was last told.
+    reported: Option<Option<ParkReason>>,
+}
+
+/// Record the latest game state seen by the telemetry source.
+pub fn observe(state: &BridgeState, status: GameStatus) {
+    state.observed().status = Some((status, Instant::now()));
+}
+
+/// Park reason for the plugin source. The plugin doesn't send game state, so this uses what the
+/// telemetry source last saw, or peeks at the telemetry itself when that's stale.
+/// Without telemetry no rules apply.
+pub fn plugin_park_reason(state: &BridgeState) -> Option<ParkReason> {
+    if !state.proximity_enabled() {
+        return Some(ParkReason::Disabled);
+    }
+    let now = Instant::now();
+    let fresh = {
+        let mut obs = state.observed();
+        let fresh = obs.status.filter(|(_, at)| now - *at < OBSERVED_MAX_AGE);
+        if fresh.is_none() {
+            if obs.last_peek.is_some_and(|t| now - t < PEEK_INTERVAL) {
+                return None;
+            }
+            obs.last_peek = Some(now);
+        }
+        fresh
+    };
+    let status = match fresh {
+        Some((status, _)) => status,
+        None => {
+            // read outside the lock, the telemetry source shouldn't wait on the shared memory
+            let status = GameStatus::from(&TelemetryFrame::from(&read_telemetry().ok()?));
+            state.observed().status = Some((status, now));
+            status
+        }
+    };
+    let cfg = get_config();
+    cfg.park.evaluate(&status, cfg.spectator.mode)
+}
+
+/// Tell the GUI about the park outcome if it changed.
+pub fn report_park_status(state: &BridgeState, reason: Option<ParkReason>, to_gui: &Sender<ToGUI>) {
+    let mut obs = state.observed();
+    if obs.reported != Some(reason) {
+        obs.reported = Some(reason);
+        if let Some(r) = reason {
+            log::info!("Parking player: {}", r);
+        }
+        let _ = to_gui.send(ToGUI::ParkStatus(reason));
+    }
+}
+
+/// Forget observed state, e.g. when the source is stopped.
+pub fn reset_park_status(state: &BridgeState) {
+    *state.observed() = Observed::default();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status(game: GameState, race: RaceState, spectating: bool) -> GameStatus {
+        GameStatus {
+            game,
+            race,
+            spectating,
+        }
+    }
+
+    const ALL_ON: ParkRules = ParkRules {
+        in_menus: true,
+        when_paused: true,
+        after_finish: true,
+    };
+    const ALL_OFF: ParkRules = ParkRules {
+        in_menus: false,
+        when_paused: false,
+        after_finish: false,
+    };
+
+    #[test]
+    fn each_rule_parks_only_when_enabled() {
+        use GameState as G;
+        use RaceState as R;
+        let hear = SpectatorMode::HearPlayers;
+        let cases = [
+            (status(G::Menus, R::BeforeStart, false), Some(ParkReason::Menus)),
+            (status(G::Starting, R::BeforeStart, false), Some(ParkReason::Menus)),
+            (status(G::Paused, R::Running, false), Some(ParkReason::Paused)),
+            (status(G::Running, R::Finished, false), Some(ParkReason::Finished)),
+            (status(G::Running, R::Running, false), None),
+            (status(G::Running, R::BeforeStart, false), None),
+        ];
+        for (s, reason) in cases {
+            assert_eq!(ALL_ON.evaluate(&s, hear), reason, "{:?}", s);
+            assert_eq!(ALL_OFF.evaluate(&s, hear), None, "{:?}", s);
+        }
+    }
+
+    #[test]
+    fn earlier_rules_win() {
+        use GameState as G;
+        let park = SpectatorMode::Park;
+        let s = status(G::Menus, RaceState::Finished, true);
+        assert_eq!(ALL_ON.evaluate(&s, park), Some(ParkReason::Menus));
+        let s = status(G::Paused, RaceState::Finished, true);
+        assert_eq!(ALL_ON.evaluate(&s, park), Some(ParkReason::Paused));
+        let s = status(G::Running, RaceState::Finished, true);
+        assert_eq!(ALL_ON.evaluate(&s, park), Some(ParkReason::Finished));
+
+        // a disabled rule falls through to the next one
+        let rules = ParkRules {
+            when_paused: false,
+            ..ALL_ON
+        };
+        let s = status(G::Paused, RaceState::Finished, false);
+        assert_eq!(rules.evaluate(&s, park), Some(ParkReason::Finished));
+    }
+
+    #[test]
+    fn spectators_follow_the_spectator_mode() {
+        let s = status(GameState::Running, RaceState::Running, true);
+        assert_eq!(ALL_OFF.evaluate(&s, SpectatorMode::Park), Some(ParkReason::Spectating));
+        assert_eq!(ALL_OFF.evaluate(&s, SpectatorMode::HearPlayers), None);
+        assert_eq!(ALL_OFF.evaluate(&s, SpectatorMode::Isolated), None);
+
+        let s = status(GameState::Running, RaceState::Running, false);
+        assert_eq!(ALL_ON.evaluate(&s, SpectatorMode::Park), None);
+    }
+
+    #[test]
+    fn defaults_only_park_in_menus() {
+        let rules = ParkRules::default();
+        let hear = SpectatorMode::HearPlayers;
+        let s = status(GameState::Menus, RaceState::BeforeStart, false);
+        assert_eq!(rules.evaluate(&s, hear), Some(ParkReason::Menus));
+        let s = status(GameState::Paused, RaceState::Finished, false);
+        assert_eq!(rules.evaluate(&s, hear), None);
+    }
+}
@@ -0,0 +1,127 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Sender;
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use sysinfo::{ProcessRefreshKind, System};
+
+use crate::app::{FromGuiToServer, ToGUI};
+use crate::bridge_state::SharedState;
+use crate::config::get_config;
+use crate::hybrid::is_plugin_connected;
+use crate::source_manager::{sleep_unless_stopped, SourceKind};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+/// Process names (lowercase, without `.exe`) of games that provide ManiaPlanet telemetry.
+const GAME_PROCESSES: &[&str] = &["trackmania", "maniaplanet", "trackmaniaturbo"];
+const MUMBLE_PROCESSES: &[&str] = &["mumble"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AutoDetectConfig {
+    /// Connect the Mumble link as soon as Mumble starts.
+    pub auto_connect_mumble: bool,
+    /// Switch from the plugin to telemetry if the plugin doesn't connect in time.
+    /// Only used when no default source is configured.
+    pub auto_select_source: bool,
+    pub plugin_timeout_secs: u32,
+}
+
+impl Default for AutoDetectConfig {
+    fn default() -> Self {
+        AutoDetectConfig {
+            auto_connect_mumble: true,
+            auto_select_source: true,
+            plugin_timeout_secs: 20,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ProcessStatus {
+    pub game_running: bool,
+    pub mumble_running: bool,
+}
+
+fn is_one_of(name: &str, names: &[&str]) -> bool {
+    let name = name.to_ascii_lowercase();
+    let name = name.strip_suffix(".exe").unwrap_or(&name);
+    names.contains(&name)
+}
+
+fn scan(sys: &mut System) -> ProcessStatus {
+    sys.refresh_processes_specifics(ProcessRefreshKind::new());
+    let mut status = ProcessStatus::default();
+    for process in sys.processes().values() {
+        let name = process.name();
+        status.game_running |= is_one_of(name, GAME_PROCESSES);
+        status.mumble_running |= is_one_of(name, MUMBLE_PROCESSES);
+    }
+    status
+}
+
+/// Poll for the game and Mumble starting or exiting, and react to it, until `stop` is set.
+pub fn spawn_process_watcher(
+    to_gui: Sender<ToGUI>,
+    to_server: Sender<FromGuiToServer>,
+    state: SharedState,
+    stop: Arc<AtomicBool>,
+) -> JoinHandle<()> {
+    thread::spawn(move || {
+        let mut sys = System::new();
+        let mut last = ProcessStatus::default();
+        let mut game_started: Option<Instant> = None;
+        // only fall back to telemetry once per game launch, so a manual choice sticks
+        let mut source_checked = false;
+        while !stop.load(Ordering::SeqCst) {
+            let status = scan(&mut sys);
+            if status != last {
+                log::info!(
+                    "Processes: game running: {}, Mumble running: {}",
+                    status.game_running,
+                    status.mumble_running
+                );
+                if to_gui.send(ToGUI::Processes(status)).is_err() {
+                    return;
+                }
+            }
+
+            let cfg = get_config().auto_detect;
+            if status.mumble_running && !last.mumble_running && cfg.auto_connect_mumble {
+                log::info!("Mumble started, connecting");
+                let _ = to_server.send(FromGuiToServer::AutoConnectMumble());
+            }
+
+            match (status.game_running, game_started) {
+                (true, None) => {
+                    game_started = Some(Instant::now());
+                    source_checked = false;
+                }
+                (false, Some(_)) => game_started = None,
+                _ => {}
+            }
+            if is_plugin_connected(&state) {
+                source_checked = true;
+            }
+            if let Some(started) = game_started {
+                let timeout = Duration::from_secs(cfg.plugin_timeout_secs as u64);
+                if !source_checked && started.elapsed() >= timeout {
+                    source_checked = true;
+                    let auto = cfg.auto_select_source && get_config().default_source.is_none();
+                    if auto && state.active_source() == Some(SourceKind::Plugin) {
+                        log::info!(
+                            "Plugin didn't connect within {}s of the game starting, using telemetry",
+                            cfg.plugin_timeout_secs
+                        );
+                        let _ = to_server.send(FromGuiToServer::UseSource(SourceKind::Telemetry));
+                    }
+                }
+            }
+
+            last = status;
+            sleep_unless_stopped(POLL_INTERVAL, &stop);
+        }
+    })
+}
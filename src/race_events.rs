@@ -0,0 +1,254 @@
+use std::fmt::{self, Display, Formatter};
+
+use crate::mp_telemetry_data::RaceState;
+use crate::telemetry_frame::{RaceInfo, TelemetryFrame};
+
+/// Discrete race events derived from consecutive telemetry frames.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RaceEvent {
+    RaceStart,
+    /// `index` is 0-based; `split_ms` is the time since the previous checkpoint (or the start).
+    Checkpoint { index: u32, time_ms: u32, split_ms: u32 },
+    Respawn { count: u32 },
+    /// `lap` is 1-based.
+    LapComplete { lap: u32, time_ms: u32, lap_time_ms: u32 },
+    Finish { time_ms: u32 },
+    /// The car was moved non-continuously (`discontinuity_count` changed).
+    Teleport { discontinuity_count: u32 },
+}
+
+impl Display for RaceEvent {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            RaceEvent::RaceStart => write!(f, "Race start"),
+            RaceEvent::Checkpoint { index, time_ms, split_ms } => write!(
+                f,
+                "CP {}: {} (+{})",
+                index + 1,
+                fmt_race_time(*time_ms),
+                fmt_race_time(*split_ms)
+            ),
+            RaceEvent::Respawn { count } => write!(f, "Respawn #{}", count),
+            RaceEvent::LapComplete { lap, time_ms, lap_time_ms } => write!(
+                f,
+                "Lap {}: {} (lap {})",
+                lap,
+                fmt_race_time(*time_ms),
+                fmt_race_time(*lap_time_ms)
+            ),
+            RaceEvent::Finish { time_ms } => write!(f, "Finish: {}", fmt_race_time(*time_ms)),
+            RaceEvent::Teleport { discontinuity_count } => {
+                write!(f, "Teleport (#{})", discontinuity_count)
+            }
+        }
+    }
+}
+
+pub fn fmt_race_time(ms: u32) -> String {
+    format!("{}:{:02}.{:03}", ms / 60000, (ms / 1000) % 60, ms % 1000)
+}
+
+#[derive(Debug, Clone, Copy)]
+struct RaceSnapshot {
+    state: RaceState,
+    nb_respawns: u32,
+    nb_checkpoints: u32,
+    discontinuity_count: u32,
+}
+
+/// Turns a stream of telemetry frames into `RaceEvent`s by comparing each frame with the last.
+#[derive(Debug, Default)]
+pub struct RaceEventDetector {
+    last: Option<RaceSnapshot>,
+}
+
+impl RaceEventDetector {
+    pub fn update(&mut self, telemetry: &TelemetryFrame) -> Vec<RaceEvent> {
+        self.update_race(&telemetry.race, telemetry.object.discontinuity_count)
+    }
+
+    fn update_race(&mut self, race: &RaceInfo, discontinuity_count: u32) -> Vec<RaceEvent> {
+        let curr = RaceSnapshot {
+            state: race.state,
+            nb_respawns: race.nb_respawns,
+            nb_checkpoints: race.checkpoint_times.len() as u32,
+            discontinuity_count,
+        };
+        let Some(last) = self.last.replace(curr) else {
+            return vec![];
+        };
+
+        let mut events = vec![];
+        if curr.state != last.state && curr.state == RaceState::Running {
+            events.push(RaceEvent::RaceStart);
+        }
+
+        // checkpoints are only appended during a run; a lower count means a restart
+        let first_new_cp = if curr.nb_checkpoints < last.nb_checkpoints {
+            0
+        } else {
+            last.nb_checkpoints
+        };
+        let cp_times = &race.checkpoint_times;
+        let per_lap = race.nb_checkpoints_per_lap;
+        for i in first_new_cp..curr.nb_checkpoints {
+            let time_ms = cp_times[i as usize];
+            let prev_ms = if i == 0 { 0 } else { cp_times[i as usize - 1] };
+            events.push(RaceEvent::Checkpoint {
+                index: i,
+                time_ms,
+                split_ms: time_ms.saturating_sub(prev_ms),
+            });
+            if per_lap > 0 && (i + 1) % per_lap == 0 {
+                let lap = (i + 1) / per_lap;
+                let lap_start_ms = if lap == 1 {
+                    0
+                } else {
+                    cp_times[(i - per_lap) as usize]
+                };
+                events.push(RaceEvent::LapComplete {
+                    lap,
+                    time_ms,
+                    lap_time_ms: time_ms.saturating_sub(lap_start_ms),
+                });
+            }
+        }
+
+        if curr.nb_respawns > last.nb_respawns {
+            events.push(RaceEvent::Respawn {
+                count: curr.nb_respawns,
+            });
+        }
+
+        if curr.state != last.state && curr.state == RaceState::Finished {
+            let time_ms = match curr.nb_checkpoints {
+                0 => race.time_ms,
+                n => cp_times[n as usize - 1],
+            };
+            events.push(RaceEvent::Finish { time_ms });
+        }
+
+        if curr.discontinuity_count != last.discontinuity_count {
+            events.push(RaceEvent::Teleport {
+                discontinuity_count: curr.discontinuity_count,
+            });
+        }
+
+        events
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn race(state: RaceState, checkpoint_times: &[u32], nb_respawns: u32) -> RaceInfo {
+        RaceInfo {
+            state,
+            time_ms: checkpoint_times.last().copied().unwrap_or(0) + 100,
+            nb_respawns,
+            checkpoint_times: checkpoint_times.to_vec(),
+            nb_checkpoints_per_lap: 2,
+            nb_laps_per_race: 2,
+            timestamp: 0,
+            start_timestamp: 0,
+        }
+    }
+
+    fn detector_at(race: &RaceInfo, discontinuity_count: u32) -> RaceEventDetector {
+        let mut detector = RaceEventDetector::default();
+        assert_eq!(detector.update_race(race, discontinuity_count), vec![]);
+        detector
+    }
+
+    #[test]
+    fn first_frame_has_no_events() {
+        let mut detector = RaceEventDetector::default();
+        assert_eq!(detector.update_race(&race(RaceState::Running, &[1000], 1), 3), vec![]);
+    }
+
+    #[test]
+    fn detects_start() {
+        let mut detector = detector_at(&race(RaceState::BeforeStart, &[], 0), 0);
+        assert_eq!(
+            detector.update_race(&race(RaceState::Running, &[], 0), 0),
+            vec![RaceEvent::RaceStart]
+        );
+        assert_eq!(detector.update_race(&race(RaceState::Running, &[], 0), 0), vec![]);
+    }
+
+    #[test]
+    fn checkpoints_and_laps() {
+        let mut detector = detector_at(&race(RaceState::Running, &[], 0), 0);
+        assert_eq!(
+            detector.update_race(&race(RaceState::Running, &[1000], 0), 0),
+            vec![RaceEvent::Checkpoint { index: 0, time_ms: 1000, split_ms: 1000 }]
+        );
+        assert_eq!(
+            detector.update_race(&race(RaceState::Running, &[1000, 2500], 0), 0),
+            vec![
+                RaceEvent::Checkpoint { index: 1, time_ms: 2500, split_ms: 1500 },
+                RaceEvent::LapComplete { lap: 1, time_ms: 2500, lap_time_ms: 2500 },
+            ]
+        );
+        // two checkpoints in one frame, completing lap 2
+        assert_eq!(
+            detector.update_race(&race(RaceState::Running, &[1000, 2500, 3200, 4000], 0), 0),
+            vec![
+                RaceEvent::Checkpoint { index: 2, time_ms: 3200, split_ms: 700 },
+                RaceEvent::Checkpoint { index: 3, time_ms: 4000, split_ms: 800 },
+                RaceEvent::LapComplete { lap: 2, time_ms: 4000, lap_time_ms: 1500 },
+            ]
+        );
+    }
+
+    #[test]
+    fn restart_counts_checkpoints_from_zero() {
+        let mut detector = detector_at(&race(RaceState::Running, &[1000, 2500, 3200], 0), 0);
+        assert_eq!(
+            detector.update_race(&race(RaceState::Running, &[900], 0), 0),
+            vec![RaceEvent::Checkpoint { index: 0, time_ms: 900, split_ms: 900 }]
+        );
+    }
+
+    #[test]
+    fn detects_respawn() {
+        let mut detector = detector_at(&race(RaceState::Running, &[1000], 0), 0);
+        assert_eq!(
+            detector.update_race(&race(RaceState::Running, &[1000], 1), 0),
+            vec![RaceEvent::Respawn { count: 1 }]
+        );
+        // a restart resets the count without a respawn
+        assert_eq!(detector.update_race(&race(RaceState::Running, &[1000], 0), 0), vec![]);
+    }
+
+    #[test]
+    fn finish_uses_last_checkpoint_or_race_time() {
+        let mut detector = detector_at(&race(RaceState::Running, &[1000, 2500, 3200], 0), 0);
+        assert_eq!(
+            detector.update_race(&race(RaceState::Finished, &[1000, 2500, 3200, 4000], 0), 0),
+            vec![
+                RaceEvent::Checkpoint { index: 3, time_ms: 4000, split_ms: 800 },
+                RaceEvent::LapComplete { lap: 2, time_ms: 4000, lap_time_ms: 1500 },
+                RaceEvent::Finish { time_ms: 4000 },
+            ]
+        );
+
+        let mut detector = detector_at(&race(RaceState::Running, &[], 0), 0);
+        assert_eq!(
+            detector.update_race(&race(RaceState::Finished, &[], 0), 0),
+            vec![RaceEvent::Finish { time_ms: 100 }]
+        );
+    }
+
+    #[test]
+    fn detects_teleport() {
+        let mut detector = detector_at(&race(RaceState::Running, &[], 0), 4);
+        assert_eq!(
+            detector.update_race(&race(RaceState::Running, &[], 1), 5),
+            vec![RaceEvent::Respawn { count: 1 }, RaceEvent::Teleport { discontinuity_count: 5 }]
+        );
+        assert_eq!(detector.update_race(&race(RaceState::Running, &[], 1), 5), vec![]);
+    }
+}
@@ -0,0 +1,156 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use cgmath::{InnerSpace, Vector3};
+use egui::{Color32, Pos2, Sense, Stroke, Vec2};
+use serde::{Deserialize, Serialize};
+
+use crate::app::{vec_flip_z, vecm, MUMBLE_SCALE_INV};
+use crate::bridge_state::LastPositions;
+use crate::config::{get_config, update_config};
+
+const MAX_SIZE: f32 = 220.0;
+/// Trail points are at least this far apart in time.
+const TRAIL_INTERVAL: Duration = Duration::from_millis(50);
+/// Enough for the longest trail setting (120s) at one point per `TRAIL_INTERVAL`.
+const MAX_TRAIL_POINTS: usize = 2400;
+const ARROW_LEN: f32 = 14.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RadarConfig {
+    /// Mumble's "Maximum Distance" (Settings > Audio Output), in meters.
+    pub audible_range_m: f32,
+    /// How much of the player's path to show.
+    pub trail_secs: f32,
+}
+
+impl Default for RadarConfig {
+    fn default() -> Self {
+        RadarConfig {
+            audible_range_m: 15.0,
+            trail_secs: 10.0,
+        }
+    }
+}
+
+/// A player or camera position in game units, with its facing direction.
+#[derive(Debug, Clone, Copy)]
+struct Marker {
+    pos: [f32; 3],
+    dir: [f32; 3],
+}
+
+/// Top-down view centred on the player: player and camera with facing arrows, the player's
+/// recent path, and a ring showing how far away others can still hear you.
+#[derive(Default)]
+pub struct Radar {
+    player: Option<Marker>,
+    camera: Option<Marker>,
+    trail: VecDeque<(Instant, [f32; 3])>,
+}
+
+impl Radar {
+    /// Take positions as sent to Mumble.
+    pub fn push(&mut self, positions: &LastPositions) {
+        let to_game = |pos: [f32; 3], dir: [f32; 3]| Marker {
+            pos: vec_flip_z(vecm(pos, MUMBLE_SCALE_INV)),
+            dir: vec_flip_z(dir),
+        };
+        let player = to_game(positions.p.pos, positions.p.dir);
+        self.player = Some(player);
+        self.camera = Some(to_game(positions.c.pos, positions.c.dir));
+        if self
+            .trail
+            .back()
+            .is_none_or(|(at, _)| positions.at.saturating_duration_since(*at) >= TRAIL_INTERVAL)
+        {
+            self.trail.push_back((positions.at, player.pos));
+        }
+        // the panel may be collapsed or the window hidden, so don't rely on `ui` to prune
+        self.prune(positions.at, get_config().radar.trail_secs);
+    }
+
+    fn prune(&mut self, now: Instant, trail_secs: f32) {
+        let trail_for = Duration::from_secs_f32(trail_secs.max(0.0));
+        while self.trail.front().is_some_and(|(at, _)| now.saturating_duration_since(*at) > trail_for) {
+            self.trail.pop_front();
+        }
+        while self.trail.len() > MAX_TRAIL_POINTS {
+            self.trail.pop_front();
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        let cfg = get_config().radar;
+        let Some(player) = self.player else {
+            ui.label("No positions yet");
+            return;
+        };
+        let now = Instant::now();
+        self.prune(now, cfg.trail_secs);
+
+        let size = ui.available_width().min(MAX_SIZE);
+        let (rect, _) = ui.allocate_exact_size(Vec2::splat(size), Sense::hover());
+        let painter = ui.painter_at(rect);
+        let visuals = ui.visuals();
+        painter.rect_filled(rect, 4.0, visuals.extreme_bg_color);
+
+        // game units; leave some room outside the ring
+        let range = cfg.audible_range_m * MUMBLE_SCALE_INV;
+        let px_per_unit = size / 2.0 / (range * 1.25).max(1.0);
+        let center = rect.center();
+        // x to the right and z down, seen from above
+        let to_screen = |p: [f32; 3]| {
+            center + Vec2::new(p[0] - player.pos[0], p[2] - player.pos[2]) * px_per_unit
+        };
+
+        painter.circle_stroke(center, range * px_per_unit, Stroke::new(1.0, visuals.weak_text_color()));
+
+        let trail_color = visuals.text_color();
+        let teleport_distance = get_config().smoothing.teleport_distance;
+        for ((at, a), (_, b)) in self.trail.iter().zip(self.trail.iter().skip(1)) {
+            // respawns and parking aren't part of the path
+            if (Vector3::from(*a) - Vector3::from(*b)).magnitude() > teleport_distance {
+                continue;
+            }
+            // older parts fade out
+            let age = now.saturating_duration_since(*at).as_secs_f32() / cfg.trail_secs.max(0.1);
+            let fade = (1.0 - age).clamp(0.0, 1.0) * 0.6;
+            painter.line_segment(
+                [to_screen(*a), to_screen(*b)],
+                Stroke::new(1.5, trail_color.gamma_multiply(fade)),
+            );
+        }
+
+        if let Some(camera) = self.camera {
+            draw_marker(&painter, to_screen(camera.pos), camera.dir, Color32::LIGHT_BLUE);
+        }
+        draw_marker(&painter, to_screen(player.pos), player.dir, Color32::LIGHT_GREEN);
+
+        ui.horizontal(|ui| {
+            ui.colored_label(Color32::LIGHT_GREEN, "● Player");
+            ui.colored_label(Color32::LIGHT_BLUE, "● Camera");
+        });
+        let mut radar = cfg;
+        ui.horizontal(|ui| {
+            ui.label("Audible range (m):")
+                .on_hover_text("Mumble's Maximum Distance, under Settings > Audio Output");
+            ui.add(egui::DragValue::new(&mut radar.audible_range_m).range(1.0..=1000.0).speed(0.5));
+            ui.label("Trail (s):");
+            ui.add(egui::DragValue::new(&mut radar.trail_secs).range(0.0..=120.0).speed(0.5));
+        });
+        if radar != cfg {
+            update_config(|c| c.radar = radar);
+        }
+    }
+}
+
+/// A dot with an arrow along the horizontal part of `dir`.
+fn draw_marker(painter: &egui::Painter, at: Pos2, dir: [f32; 3], color: Color32) {
+    painter.circle_filled(at, 4.0, color);
+    let flat = Vec2::new(dir[0], dir[2]);
+    if flat.length() > 1e-3 {
+        painter.arrow(at, flat.normalized() * ARROW_LEN, Stroke::new(2.0, color));
+    }
+}
@@ -0,0 +1,277 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use message_io::network::{Endpoint, NetEvent, Transport};
+use message_io::node;
+use serde::{Deserialize, Serialize};
+
+use crate::bridge_state::BridgeState;
+use crate::tcp_server::ToTM;
+
+/// Entries are buffered and written out at most this long after they're recorded.
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Which way a recorded frame travelled, from the bridge's point of view.
+#[derive(Debug, Deserialize, Serialize, Copy, Clone, PartialEq, Eq)]
+pub enum Direction {
+    In,
+    Out,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub enum FrameData {
+    Accepted(),
+    Disconnected(),
+    /// Binary position frame, hex encoded.
+    Binary(String),
+    /// JSON frame (`FromTM` inbound, `ToTM` outbound), stored verbatim.
+    Json(String),
+}
+
+/// One line of a session file (JSON lines).
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct SessionEntry {
+    /// Milliseconds since the recording started.
+    pub t_ms: u64,
+    pub dir: Direction,
+    pub endpoint: String,
+    pub data: FrameData,
+}
+
+pub struct SessionRecorder {
+    out: BufWriter<File>,
+    start: Instant,
+    last_flush: Instant,
+}
+
+impl SessionRecorder {
+    pub fn create(path: &Path) -> io::Result<Self> {
+        Ok(SessionRecorder {
+            out: BufWriter::new(File::create(path)?),
+            start: Instant::now(),
+            last_flush: Instant::now(),
+        })
+    }
+
+    fn write_entry(&mut self, dir: Direction, endpoint: Endpoint, data: FrameData) -> io::Result<()> {
+        let entry = SessionEntry {
+            t_ms: self.start.elapsed().as_millis() as u64,
+            dir,
+            endpoint: endpoint.addr().to_string(),
+            data,
+        };
+        serde_json::to_writer(&mut self.out, &entry)?;
+        self.out.write_all(b"\n")?;
+        // positions arrive at frame rate; flushing each one costs a write call per frame
+        if self.last_flush.elapsed() >= FLUSH_INTERVAL {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.last_flush = Instant::now();
+        self.out.flush()
+    }
+}
+
+pub fn start_recording(state: &BridgeState, path: &Path) -> io::Result<()> {
+    let recorder = SessionRecorder::create(path)?;
+    log::info!("Recording plugin session to {}", path.display());
+    *state.session_recorder() = Some(recorder);
+    Ok(())
+}
+
+pub fn stop_recording(state: &BridgeState) {
+    let Some(mut recorder) = state.session_recorder().take() else {
+        return;
+    };
+    match recorder.flush() {
+        Ok(()) => log::info!("Stopped recording plugin session"),
+        Err(e) => log::error!("Failed to write the end of the session recording: {}", e),
+    }
+}
+
+fn record(state: &BridgeState, dir: Direction, endpoint: Endpoint, data: impl FnOnce() -> FrameData) {
+    let mut recorder = state.session_recorder();
+    if let Some(r) = recorder.as_mut() {
+        if let Err(e) = r.write_entry(dir, endpoint, data()) {
+            log::error!("Failed to write session entry, stopping recording: {}", e);
+            *recorder = None;
+        }
+    }
+}
+
+/// Record a frame received from the plugin.
+pub fn record_inbound(state: &BridgeState, endpoint: Endpoint, data: &[u8]) {
+    record(state, Direction::In, endpoint, || {
+        if !data.is_empty() && data[0] == 1 {
+            FrameData::Binary(hex_encode(data))
+        } else {
+            FrameData::Json(String::from_utf8_lossy(data).to_string())
+        }
+    });
+}
+
+/// Record a message sent to the plugin.
+pub fn record_outbound(state: &BridgeState, endpoint: Endpoint, msg: &ToTM) {
+    record(state, Direction::Out, endpoint, || {
+        FrameData::Json(serde_json::to_string(msg).unwrap())
+    });
+}
+
+pub fn record_accepted(state: &BridgeState, endpoint: Endpoint) {
+    record(state, Direction::In, endpoint, FrameData::Accepted);
+}
+
+pub fn record_disconnected(state: &BridgeState, endpoint: Endpoint) {
+    record(state, Direction::In, endpoint, FrameData::Disconnected);
+}
+
+pub fn read_session(path: &Path) -> io::Result<Vec<SessionEntry>> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut entries = vec![];
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let entry = serde_json::from_str(&line).map_err(|e| {
+            io::Error::new(io::ErrorKind::InvalidData, format!("line {}: {}", i + 1, e))
+        })?;
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+/// Connect to a running bridge at `addr` and re-send the inbound traffic of a recorded
+/// session with its original timing. Each recorded plugin endpoint gets its own connection.
+/// Messages the bridge sends back are logged so they can be compared with the recording.
+pub fn replay_session(path: &Path, addr: &str) -> io::Result<()> {
+    let entries = read_session(path)?;
+    log::info!("Replaying {} entries from {} to {}", entries.len(), path.display(), addr);
+
+    let (handler, listener) = node::split::<()>();
+    let _task = listener.for_each_async(|event| match event.network() {
+        NetEvent::Message(endpoint, data) => {
+            log::info!("<- {}: {}", endpoint.addr(), String::from_utf8_lossy(data));
+        }
+        NetEvent::Disconnected(endpoint) => {
+            log::warn!("Bridge closed connection {}", endpoint.addr());
+        }
+        _ => {}
+    });
+
+    let mut connections: HashMap<String, Endpoint> = HashMap::new();
+    let start = Instant::now();
+    for entry in entries.into_iter().filter(|e| e.dir == Direction::In) {
+        let due = start + Duration::from_millis(entry.t_ms);
+        let now = Instant::now();
+        if due > now {
+            std::thread::sleep(due - now);
+        }
+
+        let bytes = match entry.data {
+            FrameData::Disconnected() => {
+                if let Some(ep) = connections.remove(&entry.endpoint) {
+                    handler.network().remove(ep.resource_id());
+                }
+                continue;
+            }
+            FrameData::Accepted() => None,
+            FrameData::Binary(hex) => Some(hex_decode(&hex).ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidData, "invalid hex in binary frame")
+            })?),
+            FrameData::Json(json) => Some(json.into_bytes()),
+        };
+
+        if !connections.contains_key(&entry.endpoint) {
+            let (ep, _) = handler.network().connect_sync(Transport::FramedTcp, addr)?;
+            log::info!("Connected to bridge for recorded endpoint {}", entry.endpoint);
+            connections.insert(entry.endpoint.clone(), ep);
+        }
+        if let Some(bytes) = bytes {
+            handler.network().send(connections[&entry.endpoint], &bytes);
+        }
+    }
+
+    // give the bridge a moment to answer the last frames before disconnecting
+    std::thread::sleep(Duration::from_millis(500));
+    handler.stop();
+    log::info!("Replay finished");
+    Ok(())
+}
+
+fn hex_encode(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn hex_decode(s: &str) -> Option<Vec<u8>> {
+    s.as_bytes()
+        .chunks(2)
+        .map(|c| match c {
+            [_, _] => u8::from_str_radix(std::str::from_utf8(c).ok()?, 16).ok(),
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recorded_frames_read_back() {
+        let path = std::env::temp_dir()
+            .join(format!("tm-mumble-link-session-test-{}.jsonl", std::process::id()));
+        let (handler, _listener) = node::split::<()>();
+        let (_, addr) = handler.network().listen(Transport::FramedTcp, "127.0.0.1:0").unwrap();
+        let (endpoint, _) = handler.network().connect_sync(Transport::FramedTcp, addr).unwrap();
+
+        let state = BridgeState::new();
+        start_recording(&state, &path).unwrap();
+        record_accepted(&state, endpoint);
+        record_inbound(&state, endpoint, &[1, 0, 0, 0x80, 0x3f]);
+        record_inbound(&state, endpoint, br#"{"Ping":[]}"#);
+        record_outbound(&state, endpoint, &ToTM::Ping());
+        record_disconnected(&state, endpoint);
+        stop_recording(&state);
+        handler.stop();
+
+        let entries = read_session(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(entries.len(), 5);
+        assert!(entries.iter().all(|e| e.endpoint == addr.to_string()));
+        assert!(entries.windows(2).all(|w| w[0].t_ms <= w[1].t_ms));
+        let dirs: Vec<_> = entries.iter().map(|e| e.dir).collect();
+        use Direction::*;
+        assert_eq!(dirs, [In, In, In, Out, In]);
+        assert!(matches!(entries[0].data, FrameData::Accepted()));
+        assert!(matches!(&entries[1].data, FrameData::Binary(hex) if hex == "010000803f"));
+        assert!(matches!(&entries[2].data, FrameData::Json(json) if json == r#"{"Ping":[]}"#));
+        let ping = serde_json::to_string(&ToTM::Ping()).unwrap();
+        assert!(matches!(&entries[3].data, FrameData::Json(json) if *json == ping));
+        assert!(matches!(entries[4].data, FrameData::Disconnected()));
+    }
+
+    #[test]
+    fn hex_round_trip() {
+        let data: Vec<u8> = (0..=255).collect();
+        let hex = hex_encode(&data);
+        assert_eq!(hex.len(), 512);
+        assert!(hex.starts_with("000102") && hex.ends_with("fdfeff"));
+        assert_eq!(hex_decode(&hex), Some(data));
+        assert_eq!(hex_decode(""), Some(vec![]));
+        assert_eq!(hex_decode("01FF"), Some(vec![1, 255]));
+    }
+
+    #[test]
+    fn hex_decode_rejects_bad_input() {
+        assert_eq!(hex_decode("abc"), None);
+        assert_eq!(hex_decode("zz"), None);
+        assert_eq!(hex_decode("é0"), None);
+    }
+}
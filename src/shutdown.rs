@@ -0,0 +1,200 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, OnceLock, TryLockError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use egui::Context;
+
+use crate::app::FromGuiToServer;
+use crate::bridge_state::{BridgeState, SharedState};
+use crate::maniaplanet_telemetry::position_near_zero;
+use crate::session_log;
+use crate::tcp_server::shutdown_tcp_server;
+use crate::telemetry_export;
+
+/// How long to wait for the server thread (and the source threads it joins) to finish.
+const JOIN_TIMEOUT: Duration = Duration::from_secs(3);
+/// Exit anyway if the GUI hasn't closed this long after an exit request.
+const FORCE_EXIT_AFTER: Duration = Duration::from_secs(10);
+const SIGNAL_POLL: Duration = Duration::from_millis(100);
+
+/// Set from signal handlers, which can't do anything else safely.
+static SIGNALLED: AtomicBool = AtomicBool::new(false);
+
+/// Starts an exit from outside the GUI: the tray, signals, fatal errors. It closes the window,
+/// and `main` runs `shutdown` once the GUI has exited, so every exit takes the same path.
+#[derive(Clone)]
+pub struct ShutdownTrigger {
+    state: SharedState,
+    ctx: Arc<OnceLock<Context>>,
+}
+
+impl ShutdownTrigger {
+    pub fn new(state: SharedState) -> Self {
+        ShutdownTrigger {
+            state,
+            ctx: Arc::new(OnceLock::new()),
+        }
+    }
+
+    pub fn set_context(&self, ctx: Context) {
+        let _ = self.ctx.set(ctx);
+        // an exit requested before the window existed
+        if self.state.is_shutting_down() {
+            self.close_window();
+        }
+    }
+
+    pub fn trigger(&self, reason: &str) {
+        if !self.state.request_shutdown() {
+            return;
+        }
+        log::info!("Exit requested: {}", reason);
+        self.close_window();
+        let state = self.state.clone();
+        thread::spawn(move || {
+            thread::sleep(FORCE_EXIT_AFTER);
+            log::error!("The window didn't close within {:?}, exiting", FORCE_EXIT_AFTER);
+            shutdown_tcp_server(&state);
+            std::process::exit(1);
+        });
+    }
+
+    fn close_window(&self) {
+        if let Some(ctx) = self.ctx.get() {
+            ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+            ctx.request_repaint();
+        }
+    }
+}
+
+/// Tell the plugin, park the player and stop the sources, then wait for the server thread.
+/// The server thread does the Mumble and plugin side when it gets `Shutdown`.
+pub fn shutdown(
+    state: &BridgeState,
+    to_server: &Sender<FromGuiToServer>,
+    server: JoinHandle<()>,
+    watcher: JoinHandle<()>,
+    watcher_stop: &AtomicBool,
+) {
+    state.request_shutdown();
+    log::info!("Shutting down");
+    // first, so it doesn't switch sources or reconnect Mumble while the server stops
+    watcher_stop.store(true, Ordering::SeqCst);
+    if !join_with_timeout(watcher, JOIN_TIMEOUT) {
+        log::warn!("Process watcher didn't stop within {:?}", JOIN_TIMEOUT);
+    }
+    telemetry_export::stop_export(state);
+    session_log::stop_recording(state);
+    if to_server.send(FromGuiToServer::Shutdown()).is_err() {
+        // the server thread is already gone; at least tell the plugin
+        shutdown_tcp_server(state);
+    }
+    if join_with_timeout(server, JOIN_TIMEOUT) {
+        log::info!("Shutdown complete");
+    } else {
+        log::warn!("Server thread didn't stop within {:?}", JOIN_TIMEOUT);
+    }
+}
+
+/// Returns false if the thread was still running after `timeout`.
+pub fn join_with_timeout(handle: JoinHandle<()>, timeout: Duration) -> bool {
+    let until = Instant::now() + timeout;
+    while !handle.is_finished() {
+        if Instant::now() >= until {
+            return false;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    if handle.join().is_err() {
+        log::error!("Thread panicked while shutting down");
+    }
+    true
+}
+
+/// Exit cleanly on Ctrl+C / SIGTERM (and console close on Windows).
+pub fn install_signal_handlers(trigger: ShutdownTrigger) {
+    if let Err(e) = platform::install() {
+        log::warn!("Failed to install signal handlers: {}", e);
+        return;
+    }
+    thread::spawn(move || loop {
+        thread::sleep(SIGNAL_POLL);
+        if SIGNALLED.load(Ordering::SeqCst) {
+            trigger.trigger("signal");
+            return;
+        }
+    });
+}
+
+/// On a panic anywhere, park the player and tell the plugin right away, since release builds
+/// abort after the hook and nothing else gets to run. Then try the normal exit.
+pub fn install_panic_hook(trigger: ShutdownTrigger) {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        default_hook(info);
+        log::error!("Fatal error: {}", info);
+        park_now(&trigger.state);
+        shutdown_tcp_server(&trigger.state);
+        trigger.trigger("fatal error");
+    }));
+}
+
+/// Best effort: skipped if the link is locked, which may be by the panicking thread itself.
+fn park_now(state: &BridgeState) {
+    let Some(mumble) = state.mumble() else {
+        return;
+    };
+    let mut link = match mumble.try_lock() {
+        Ok(link) => link,
+        Err(TryLockError::Poisoned(e)) => e.into_inner(),
+        Err(TryLockError::WouldBlock) => {
+            log::warn!("Mumble link busy, not parking the player");
+            return;
+        }
+    };
+    if let Ok(mumble) = link.as_mut() {
+        // the context only reaches Mumble with the next update
+        mumble.set_context(b"");
+        mumble.update(position_near_zero(), position_near_zero());
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use std::io;
+    use std::sync::atomic::Ordering;
+
+    use windows::Win32::Foundation::{BOOL, TRUE};
+    use windows::Win32::System::Console::SetConsoleCtrlHandler;
+
+    unsafe extern "system" fn on_ctrl(_ctrl_type: u32) -> BOOL {
+        super::SIGNALLED.store(true, Ordering::SeqCst);
+        TRUE
+    }
+
+    pub fn install() -> io::Result<()> {
+        unsafe { SetConsoleCtrlHandler(Some(Some(on_ctrl)), true) }.map_err(io::Error::other)
+    }
+}
+
+#[cfg(unix)]
+mod platform {
+    use std::io;
+    use std::sync::atomic::Ordering;
+
+    extern "C" fn on_signal(_signal: libc::c_int) {
+        super::SIGNALLED.store(true, Ordering::SeqCst);
+    }
+
+    pub fn install() -> io::Result<()> {
+        for signal in [libc::SIGINT, libc::SIGTERM] {
+            let handler = on_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+            if unsafe { libc::signal(signal, handler) } == libc::SIG_ERR {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,106 @@
+use std::fs;
+use std::io::{self, Read, Write};
+use std::net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::sync::{Arc, OnceLock};
+use std::thread;
+use std::time::Duration;
+
+use egui::Context;
+
+use crate::bridge_state::SharedState;
+use crate::config::config_dir;
+use crate::set_window_visible;
+
+const LOCK_FILE_NAME: &str = "instance.lock";
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(1);
+const SHOW_REQUEST: &[u8] = b"tm-mumble-link show\n";
+const SHOW_REPLY: &[u8] = b"ok\n";
+/// Running, but the window doesn't exist yet.
+const NOT_SHOWN_REPLY: &[u8] = b"no\n";
+
+/// Proof that this is the only running bridge. The lock file holds the port of a loopback
+/// listener, so a second copy can ask us to show our window instead of starting.
+/// A lock file left behind by a crash is detected because nobody answers on that port.
+pub struct InstanceGuard {
+    path: PathBuf,
+    window: Arc<OnceLock<(Context, SharedState)>>,
+}
+
+pub enum Instance {
+    First(InstanceGuard),
+    /// Another bridge is running and was asked to show its window; `shown` is false if it
+    /// couldn't, e.g. because it's still starting.
+    Existing { shown: bool },
+}
+
+impl InstanceGuard {
+    /// Called once the window exists, so show requests can reach it.
+    pub fn set_window(&self, ctx: Context, state: SharedState) {
+        let _ = self.window.set((ctx, state));
+    }
+}
+
+impl Drop for InstanceGuard {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+pub fn claim_instance() -> io::Result<Instance> {
+    let path = config_dir().join(LOCK_FILE_NAME);
+    if let Ok(s) = fs::read_to_string(&path) {
+        match s.trim().parse::<u16>() {
+            Ok(port) => match ask_to_show(port) {
+                Some(shown) => return Ok(Instance::Existing { shown }),
+                None => log::info!("Replacing stale instance lock {}", path.display()),
+            },
+            Err(_) => log::info!("Replacing stale instance lock {}", path.display()),
+        }
+    }
+
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))?;
+    let port = listener.local_addr()?.port();
+    fs::create_dir_all(config_dir())?;
+    fs::write(&path, port.to_string())?;
+    let window = Arc::new(OnceLock::new());
+    let w = window.clone();
+    thread::spawn(move || serve_show_requests(listener, w));
+    Ok(Instance::First(InstanceGuard { path, window }))
+}
+
+/// Whether the window was shown, or `None` if no bridge answered.
+fn ask_to_show(port: u16) -> Option<bool> {
+    let addr = SocketAddr::from((Ipv4Addr::LOCALHOST, port));
+    let mut stream = TcpStream::connect_timeout(&addr, HANDSHAKE_TIMEOUT).ok()?;
+    let _ = stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT));
+    stream.write_all(SHOW_REQUEST).ok()?;
+    let mut reply = [0u8; SHOW_REPLY.len()];
+    stream.read_exact(&mut reply).ok()?;
+    match &reply[..] {
+        SHOW_REPLY => Some(true),
+        NOT_SHOWN_REPLY => Some(false),
+        _ => None,
+    }
+}
+
+fn serve_show_requests(listener: TcpListener, window: Arc<OnceLock<(Context, SharedState)>>) {
+    for stream in listener.incoming() {
+        let Ok(mut stream) = stream else {
+            continue;
+        };
+        let _ = stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT));
+        let mut request = [0u8; SHOW_REQUEST.len()];
+        if stream.read_exact(&mut request).is_err() || request != SHOW_REQUEST {
+            continue;
+        }
+        log::info!("Another copy of the bridge was started, showing the window");
+        let Some((ctx, state)) = window.get() else {
+            let _ = stream.write_all(NOT_SHOWN_REPLY);
+            continue;
+        };
+        set_window_visible(ctx, state, true);
+        ctx.send_viewport_cmd(egui::ViewportCommand::Focus);
+        let _ = stream.write_all(SHOW_REPLY);
+    }
+}
@@ -0,0 +1,329 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use cgmath::{InnerSpace, Vector3};
+use mumble_link::{MumbleLink, Position};
+use serde::{Deserialize, Serialize};
+
+use crate::app::MUMBLE_SCALE;
+use crate::bridge_state::{BridgeState, SharedState};
+use crate::config::get_config;
+use crate::maniaplanet_telemetry::position_near_zero;
+use crate::tcp_server::SharedMumble;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SmoothingConfig {
+    pub enabled: bool,
+    /// How often Mumble is updated while smoothing is enabled.
+    pub update_rate_hz: f32,
+    /// Render this far in the past so there are two samples to interpolate between.
+    pub interpolation_delay_ms: u32,
+    /// Stop extrapolating this long after the last sample.
+    pub max_extrapolation_ms: u32,
+    /// Jumps further than this (game units) between samples snap instead of blending.
+    pub teleport_distance: f32,
+}
+
+impl Default for SmoothingConfig {
+    fn default() -> Self {
+        SmoothingConfig {
+            enabled: false,
+            update_rate_hz: 60.0,
+            interpolation_delay_ms: 30,
+            max_extrapolation_ms: 250,
+            teleport_distance: 200.0,
+        }
+    }
+}
+
+/// Player and camera positions from a source, in Mumble units.
+#[derive(Clone, Copy)]
+pub struct PositionSample {
+    pub p: Position,
+    pub c: Position,
+    /// World velocity in Mumble units/s, if the source knows it. Otherwise it's estimated.
+    pub velocity: Option<[f32; 3]>,
+    /// The source knows this sample is discontinuous with the last (respawn, parked, ...).
+    pub teleport: bool,
+}
+
+impl PositionSample {
+    pub fn new(p: Position, c: Position) -> Self {
+        PositionSample {
+            p,
+            c,
+            velocity: None,
+            teleport: false,
+        }
+    }
+
+    pub fn with_velocity(mut self, velocity: [f32; 3]) -> Self {
+        self.velocity = Some(velocity);
+        self
+    }
+
+    pub fn with_teleport(mut self, teleport: bool) -> Self {
+        self.teleport = teleport;
+        self
+    }
+}
+
+#[derive(Clone, Copy)]
+struct TimedSample {
+    at: Instant,
+    p: Position,
+    c: Position,
+    p_vel: Vector3<f32>,
+    c_vel: Vector3<f32>,
+}
+
+/// Keeps the last two samples and produces positions for arbitrary times between and after them.
+#[derive(Default)]
+pub struct PositionSmoother {
+    prev: Option<TimedSample>,
+    last: Option<TimedSample>,
+}
+
+impl PositionSmoother {
+    pub fn push(&mut self, sample: PositionSample, now: Instant, cfg: &SmoothingConfig) {
+        let p_pos = Vector3::from(sample.p.position);
+        let c_pos = Vector3::from(sample.c.position);
+        let teleport = sample.teleport
+            || self.last.is_some_and(|l| {
+                (p_pos - Vector3::from(l.p.position)).magnitude()
+                    > cfg.teleport_distance * MUMBLE_SCALE
+            });
+
+        let (p_vel, c_vel) = match (sample.velocity, self.last) {
+            _ if teleport => (Vector3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 0.0, 0.0)),
+            (Some(v), _) => (v.into(), v.into()),
+            (None, Some(l)) if now > l.at => {
+                let dt = (now - l.at).as_secs_f32();
+                (
+                    (p_pos - Vector3::from(l.p.position)) / dt,
+                    (c_pos - Vector3::from(l.c.position)) / dt,
+                )
+            }
+            (None, _) => (Vector3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 0.0, 0.0)),
+        };
+
+        self.prev = if teleport { None } else { self.last };
+        self.last = Some(TimedSample {
+            at: now,
+            p: sample.p,
+            c: sample.c,
+            p_vel,
+            c_vel,
+        });
+    }
+
+    /// Interpolate between the last two samples, or extrapolate from the last one using velocity.
+    pub fn sample_at(&self, now: Instant, cfg: &SmoothingConfig) -> Option<(Position, Position)> {
+        let last = self.last?;
+        let render_at = now
+            .checked_sub(Duration::from_millis(cfg.interpolation_delay_ms as u64))
+            .unwrap_or(now);
+
+        if let Some(prev) = self.prev.filter(|_| render_at < last.at) {
+            let span = (last.at - prev.at).as_secs_f32();
+            let t = match render_at.checked_duration_since(prev.at) {
+                Some(d) if span > 0.0 => d.as_secs_f32() / span,
+                _ => 0.0,
+            };
+            return Some((lerp_pos(&prev.p, &last.p, t), lerp_pos(&prev.c, &last.c, t)));
+        }
+
+        let max_dt = cfg.max_extrapolation_ms as f32 / 1000.0;
+        let dt = render_at
+            .checked_duration_since(last.at)
+            .map_or(0.0, |d| d.as_secs_f32())
+            .min(max_dt);
+        Some((extrapolate(&last.p, last.p_vel, dt), extrapolate(&last.c, last.c_vel, dt)))
+    }
+}
+
+fn lerp_pos(a: &Position, b: &Position, t: f32) -> Position {
+    let lerp = |a: [f32; 3], b: [f32; 3]| Vector3::from(a) + (Vector3::from(b) - Vector3::from(a)) * t;
+    let normed = |v: Vector3<f32>, fallback: [f32; 3]| {
+        if v.magnitude2() > 1e-9 {
+            v.normalize().into()
+        } else {
+            fallback
+        }
+    };
+    Position {
+        position: lerp(a.position, b.position).into(),
+        front: normed(lerp(a.front, b.front), b.front),
+        top: normed(lerp(a.top, b.top), b.top),
+    }
+}
+
+fn extrapolate(pos: &Position, vel: Vector3<f32>, dt: f32) -> Position {
+    Position {
+        position: (Vector3::from(pos.position) + vel * dt).into(),
+        ..*pos
+    }
+}
+
+/// Entry point for all sources: update Mumble directly, or hand the sample to the smoothing
+/// thread when smoothing is enabled.
+pub fn update_positions(mumble: &mut MumbleLink, state: &BridgeState, sample: PositionSample) {
+    let cfg = get_config().smoothing;
+    if cfg.enabled {
+        state.smoother().push(sample, Instant::now(), &cfg);
+    } else {
+        mumble.update(sample.p, sample.c);
+        state.update_stats(|s| s.mumble_updates += 1);
+    }
+}
+
+/// Park the player right away, bypassing the smoothing delay, e.g. when the smoothing thread is
+/// already stopped at shutdown. The smoother snaps there too, so it doesn't blend back.
+pub fn park_immediately(mumble: &mut MumbleLink, state: &BridgeState) {
+    let parked = position_near_zero();
+    let cfg = get_config().smoothing;
+    if cfg.enabled {
+        let sample = PositionSample::new(parked, parked).with_teleport(true);
+        state.smoother().push(sample, Instant::now(), &cfg);
+    }
+    mumble.update(parked, parked);
+    state.update_stats(|s| s.mumble_updates += 1);
+}
+
+/// Update Mumble at a fixed rate from the smoothed samples while smoothing is enabled,
+/// until `stop` is set.
+pub fn spawn_smoothing_thread(
+    mumble: SharedMumble,
+    state: SharedState,
+    stop: Arc<AtomicBool>,
+) -> JoinHandle<()> {
+    thread::spawn(move || {
+        while !stop.load(Ordering::Relaxed) {
+            smoothing_tick(&mumble, &state);
+        }
+    })
+}
+
+/// One Mumble update, or a short wait while smoothing is off.
+fn smoothing_tick(mumble: &SharedMumble, state: &BridgeState) {
+    let cfg = get_config().smoothing;
+    if !cfg.enabled {
+        // drop old samples, so turning it back on doesn't blend from where we were back then
+        *state.smoother() = PositionSmoother::default();
+        thread::sleep(Duration::from_millis(100));
+        return;
+    }
+    thread::sleep(Duration::from_secs_f32(1.0 / cfg.update_rate_hz.clamp(1.0, 1000.0)));
+    let Some((p, c)) = state.smoother().sample_at(Instant::now(), &cfg) else {
+        return;
+    };
+    if let Ok(mumble) = mumble.lock().unwrap().as_mut() {
+        mumble.update(p, c);
+        state.update_stats(|s| s.mumble_updates += 1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pos(x: f32) -> Position {
+        Position {
+            position: [x, 0.0, 0.0],
+            front: [0.0, 0.0, 1.0],
+            top: [0.0, 1.0, 0.0],
+        }
+    }
+
+    fn sample(x: f32) -> PositionSample {
+        PositionSample::new(pos(x), pos(x + 1.0))
+    }
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    fn x_at(smoother: &PositionSmoother, at: Instant, cfg: &SmoothingConfig) -> (f32, f32) {
+        let (p, c) = smoother.sample_at(at, cfg).unwrap();
+        (p.position[0], c.position[0])
+    }
+
+    fn assert_near(actual: (f32, f32), expected: (f32, f32)) {
+        assert!(
+            (actual.0 - expected.0).abs() < 1e-4 && (actual.1 - expected.1).abs() < 1e-4,
+            "{:?} != {:?}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn nothing_to_sample_before_the_first_push() {
+        let smoother = PositionSmoother::default();
+        assert!(smoother.sample_at(Instant::now(), &SmoothingConfig::default()).is_none());
+    }
+
+    #[test]
+    fn interpolates_behind_the_last_sample() {
+        let cfg = SmoothingConfig::default();
+        let t0 = Instant::now();
+        let mut smoother = PositionSmoother::default();
+        smoother.push(sample(0.0), t0, &cfg);
+        smoother.push(sample(1.0), t0 + ms(100), &cfg);
+
+        let delay = ms(cfg.interpolation_delay_ms as u64);
+        assert_near(x_at(&smoother, t0 + delay, &cfg), (0.0, 1.0));
+        assert_near(x_at(&smoother, t0 + ms(50) + delay, &cfg), (0.5, 1.5));
+        assert_near(x_at(&smoother, t0 + ms(100) + delay, &cfg), (1.0, 2.0));
+    }
+
+    #[test]
+    fn extrapolates_with_estimated_velocity_up_to_the_limit() {
+        let cfg = SmoothingConfig::default();
+        let t0 = Instant::now();
+        let mut smoother = PositionSmoother::default();
+        smoother.push(sample(0.0), t0, &cfg);
+        smoother.push(sample(1.0), t0 + ms(100), &cfg);
+
+        // 10 units/s, estimated from the last two samples
+        let last = t0 + ms(100) + ms(cfg.interpolation_delay_ms as u64);
+        assert_near(x_at(&smoother, last + ms(50), &cfg), (1.5, 2.5));
+        let limit = cfg.max_extrapolation_ms as f32 / 1000.0 * 10.0;
+        assert_near(x_at(&smoother, last + ms(5000), &cfg), (1.0 + limit, 2.0 + limit));
+    }
+
+    #[test]
+    fn uses_the_sources_velocity() {
+        let cfg = SmoothingConfig::default();
+        let t0 = Instant::now();
+        let mut smoother = PositionSmoother::default();
+        smoother.push(sample(0.0).with_velocity([2.0, 0.0, 0.0]), t0, &cfg);
+
+        let last = t0 + ms(cfg.interpolation_delay_ms as u64);
+        assert_near(x_at(&smoother, last + ms(100), &cfg), (0.2, 1.2));
+    }
+
+    #[test]
+    fn snaps_on_teleports() {
+        let cfg = SmoothingConfig::default();
+        let far = cfg.teleport_distance * MUMBLE_SCALE * 2.0;
+        let t0 = Instant::now();
+        let delay = ms(cfg.interpolation_delay_ms as u64);
+
+        let mut smoother = PositionSmoother::default();
+        smoother.push(sample(0.0), t0, &cfg);
+        smoother.push(sample(far), t0 + ms(100), &cfg);
+        // no blending from the old position, and no velocity from the jump
+        assert_near(x_at(&smoother, t0 + ms(50) + delay, &cfg), (far, far + 1.0));
+        assert_near(x_at(&smoother, t0 + ms(200) + delay, &cfg), (far, far + 1.0));
+
+        // short jumps only snap if the source says so
+        let mut smoother = PositionSmoother::default();
+        smoother.push(sample(0.0), t0, &cfg);
+        smoother.push(sample(1.0).with_teleport(true), t0 + ms(100), &cfg);
+        assert_near(x_at(&smoother, t0 + ms(50) + delay, &cfg), (1.0, 2.0));
+    }
+}
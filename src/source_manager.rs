@@ -0,0 +1,170 @@
+use std::fmt::{self, Display, Formatter};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Sender;
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::app::ToGUI;
+use crate::bridge_state::SharedState;
+use crate::config::get_config;
+use crate::hybrid::set_hybrid_enabled;
+use crate::maniaplanet_telemetry::run_mp_telemetry_loop;
+use crate::park_rules::reset_park_status;
+use crate::smoothing::park_immediately;
+use crate::tcp_server::{run_plugin_server, stop_tcp_server, SharedMumble};
+
+/// Where positions and context come from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SourceKind {
+    /// The Openplanet plugin connects to our socket server.
+    Plugin,
+    /// We read the game's ManiaPlanet telemetry shared memory.
+    Telemetry,
+    /// Both: the plugin's server/team context with telemetry positions.
+    Hybrid,
+}
+
+impl SourceKind {
+    pub const ALL: [SourceKind; 3] = [SourceKind::Plugin, SourceKind::Telemetry, SourceKind::Hybrid];
+
+    pub fn uses_plugin(&self) -> bool {
+        matches!(self, SourceKind::Plugin | SourceKind::Hybrid)
+    }
+
+    pub fn uses_telemetry(&self) -> bool {
+        matches!(self, SourceKind::Telemetry | SourceKind::Hybrid)
+    }
+}
+
+impl Display for SourceKind {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            SourceKind::Plugin => write!(f, "Plugin"),
+            SourceKind::Telemetry => write!(f, "Telemetry"),
+            SourceKind::Hybrid => write!(f, "Hybrid"),
+        }
+    }
+}
+
+struct RunningSource {
+    kind: SourceKind,
+    stop: Arc<AtomicBool>,
+    threads: Vec<JoinHandle<()>>,
+}
+
+/// Owns the threads of the active source and can swap it for another while keeping the Mumble link.
+pub struct SourceManager {
+    ip_addr: String,
+    /// 0 to use the configured port.
+    port: u16,
+    mumble: SharedMumble,
+    state: SharedState,
+    to_gui: Sender<ToGUI>,
+    active: Option<RunningSource>,
+}
+
+impl SourceManager {
+    pub fn new(
+        ip_addr: &str,
+        port: u16,
+        mumble: SharedMumble,
+        state: SharedState,
+        to_gui: Sender<ToGUI>,
+    ) -> Self {
+        SourceManager {
+            ip_addr: ip_addr.to_string(),
+            port,
+            mumble,
+            state,
+            to_gui,
+            active: None,
+        }
+    }
+
+    #[allow(unused)]
+    pub fn active(&self) -> Option<SourceKind> {
+        self.active.as_ref().map(|s| s.kind)
+    }
+
+    pub fn switch_to(&mut self, kind: SourceKind) {
+        if self.active.as_ref().is_some_and(|s| s.kind == kind) {
+            return;
+        }
+        self.stop();
+        log::info!("Starting source: {}", kind);
+
+        set_hybrid_enabled(&self.state, kind == SourceKind::Hybrid);
+        let stop = Arc::new(AtomicBool::new(false));
+        let mut threads = vec![];
+        if kind.uses_plugin() {
+            let ip_addr = self.ip_addr.clone();
+            let port = match self.port {
+                0 => get_config().plugin_server.port,
+                port => port,
+            };
+            let mumble = self.mumble.clone();
+            let state = self.state.clone();
+            let to_gui = self.to_gui.clone();
+            let stop = stop.clone();
+            threads.push(thread::spawn(move || {
+                run_plugin_server(&ip_addr, port, mumble, state, to_gui, &stop)
+            }));
+        }
+        if kind.uses_telemetry() {
+            let mumble = self.mumble.clone();
+            let state = self.state.clone();
+            let to_gui = self.to_gui.clone();
+            let stop = stop.clone();
+            threads.push(thread::spawn(move || {
+                let _ = run_mp_telemetry_loop(&mumble, &state, &to_gui, &stop);
+            }));
+        }
+        self.active = Some(RunningSource { kind, stop, threads });
+        self.state.set_active_source(Some(kind));
+        let _ = self.to_gui.send(ToGUI::SourceChanged(Some(kind)));
+    }
+
+    /// Stop the active source (if any), wait for its thread and park the player.
+    pub fn stop(&mut self) {
+        let Some(src) = self.active.take() else {
+            return;
+        };
+        log::info!("Stopping source: {}", src.kind);
+        self.state.set_active_source(None);
+        src.stop.store(true, Ordering::SeqCst);
+        if src.kind.uses_plugin() {
+            stop_tcp_server(&self.state);
+        }
+        for thread in src.threads {
+            if thread.join().is_err() {
+                log::error!("Source thread for {} panicked", src.kind);
+            }
+        }
+        set_hybrid_enabled(&self.state, false);
+
+        if let Ok(mumble) = self.mumble.lock().unwrap().as_mut() {
+            // the context only reaches Mumble with the next update
+            mumble.set_context(b"");
+            park_immediately(mumble, &self.state);
+        }
+        self.state.clear_context();
+        reset_park_status(&self.state);
+        let _ = self.to_gui.send(ToGUI::SourceChanged(None));
+    }
+}
+
+/// Sleep for `dur`, waking early if `stop` is set. Returns false if stopped.
+pub fn sleep_unless_stopped(dur: Duration, stop: &AtomicBool) -> bool {
+    let until = Instant::now() + dur;
+    while !stop.load(Ordering::SeqCst) {
+        let now = Instant::now();
+        if now >= until {
+            return true;
+        }
+        thread::sleep((until - now).min(Duration::from_millis(50)));
+    }
+    false
+}
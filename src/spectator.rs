@@ -0,0 +1,62 @@
+use serde::{Deserialize, Serialize};
+
+/// What to do when telemetry shows someone else's car (spectating or watching a replay).
+/// Mumble only applies positional audio between users with the same context, so the context
+/// decides which group a spectator hears positionally.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SpectatorMode {
+    /// Move out of hearing range, like when not spawned.
+    Park,
+    /// Stay in the players' context at the spectated car, so nearby players are heard positionally.
+    HearPlayers,
+    /// Use a separate spectators context: spectators only hear each other positionally.
+    Isolated,
+}
+
+impl SpectatorMode {
+    pub const ALL: [SpectatorMode; 3] = [
+        SpectatorMode::Park,
+        SpectatorMode::HearPlayers,
+        SpectatorMode::Isolated,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            SpectatorMode::Park => "Park",
+            SpectatorMode::HearPlayers => "Hear players",
+            SpectatorMode::Isolated => "Isolated",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SpectatorListenAt {
+    /// The spectated car.
+    Car,
+    /// The estimated camera behind the spectated car.
+    Camera,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SpectatorConfig {
+    pub mode: SpectatorMode,
+    pub listen_at: SpectatorListenAt,
+}
+
+impl Default for SpectatorConfig {
+    fn default() -> Self {
+        SpectatorConfig {
+            mode: SpectatorMode::Park,
+            listen_at: SpectatorListenAt::Camera,
+        }
+    }
+}
+
+/// Suffix for the Mumble context of a spectator; players use none.
+pub fn spectator_context_suffix(mode: SpectatorMode) -> &'static str {
+    match mode {
+        SpectatorMode::Isolated => "|Spectators",
+        SpectatorMode::Park | SpectatorMode::HearPlayers => "",
+    }
+}
@@ -1,19 +1,45 @@
 use std::net::SocketAddr;
 use std::sync::mpsc::{Receiver, SendError, Sender, TryRecvError};
-use std::sync::{Arc, Mutex, RwLock};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
 
 use byteorder::{LittleEndian, ReadBytesExt};
-use lazy_static::lazy_static;
 use message_io::network::{Endpoint, NetEvent, Transport};
 use message_io::node::{self};
 use mumble_link::{MumbleLink, Position};
 use serde::{Deserialize, Serialize};
 
 use crate::app::{FromGuiToServer, ToGUI};
-use crate::maniaplanet_telemetry::run_mp_telemetry_loop;
-use crate::VISIBLE;
+use crate::bridge_state::{BridgeState, SharedState};
+use crate::config::get_config;
+use crate::hybrid::{
+    accept_sample, plugin_may_set_context, set_plugin_connected, set_plugin_has_server, SampleOrigin,
+};
+use crate::maniaplanet_telemetry::position_near_zero;
+use crate::park_rules::{plugin_park_reason, report_park_status, ParkReason};
+use crate::session_log;
+use crate::source_manager::SourceManager;
+use crate::smoothing::{spawn_smoothing_thread, update_positions, PositionSample};
+
+pub const DEFAULT_PORT: u16 = 46323;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PluginServerConfig {
+    /// Port the plugin connects to.
+    pub port: u16,
+    /// How many ports after `port` to try if it's taken by another program.
+    pub fallback_ports: u16,
+}
 
-const DEFAULT_PORT: u16 = 46323;
+impl Default for PluginServerConfig {
+    fn default() -> Self {
+        PluginServerConfig {
+            port: DEFAULT_PORT,
+            fallback_ports: 10,
+        }
+    }
+}
 
 #[derive(Debug, Deserialize, Serialize, Copy, Clone)]
 pub struct MPos {
@@ -104,17 +130,29 @@ pub enum ToTM {
 
 type LE = LittleEndian;
 
-lazy_static! {
-    pub static ref LAST_CONTEXT: Mutex<String> = Mutex::new(String::new());
-    pub static ref TCP_HANDLER: Arc<RwLock<Option<node::NodeHandler<()>>>> = Arc::new(RwLock::new(None));
-    pub static ref LAST_ENDPOINT: Mutex<Option<Endpoint>> = Mutex::new(None);
+/// The Mumble link, shared between the active source and the smoothing thread.
+pub type SharedMumble = Arc<Mutex<std::io::Result<MumbleLink>>>;
+
+/// The plugin's positions, or the parked position if a park rule applies.
+fn plugin_sample(p: MPos, c: MPos, park: Option<ParkReason>) -> PositionSample {
+    match park {
+        Some(_) => PositionSample::new(position_near_zero(), position_near_zero()).with_teleport(true),
+        None => PositionSample::new(p.into(), c.into()),
+    }
 }
 
-#[allow(unused)]
-pub fn shutdown_tcp_server() {
-    if let Some(handler) = TCP_HANDLER.write().unwrap().take() {
-        if let Some(endpoint) = LAST_ENDPOINT.lock().unwrap().take() {
-            handler.network().send(endpoint, serde_json::to_string(&ToTM::ShutdownNow {}).unwrap().as_bytes());
+/// Stop the plugin server without telling the plugin to shut down, e.g. when switching sources.
+pub fn stop_tcp_server(state: &BridgeState) {
+    if let Some(handler) = state.take_plugin_handler() {
+        handler.stop();
+    }
+    state.take_plugin_endpoint();
+}
+
+pub fn shutdown_tcp_server(state: &BridgeState) {
+    if let Some(handler) = state.take_plugin_handler() {
+        if let Some(endpoint) = state.take_plugin_endpoint() {
+            send_to_tm(state, &handler, endpoint, &ToTM::ShutdownNow {});
         }
         handler.stop();
     }
@@ -123,6 +161,7 @@ pub fn shutdown_tcp_server() {
 pub fn server_main(
     ip_addr: &str,
     port: u16,
+    state: SharedState,
     to_gui: Sender<ToGUI>,
     from_gui: Receiver<FromGuiToServer>,
 ) {
@@ -131,20 +170,26 @@ pub fn server_main(
     } else {
         ip_addr
     };
-    let port = if port == 0 { DEFAULT_PORT } else { port };
 
-    let mumble: Arc<RwLock<std::io::Result<MumbleLink>>> = Arc::new(RwLock::new(Err(
-        std::io::Error::new(std::io::ErrorKind::Other, "Mumble not connected"),
-    )));
-    try_connect_mumble(&mumble, &to_gui);
+    let mumble: SharedMumble = Arc::new(Mutex::new(Err(std::io::Error::new(
+        std::io::ErrorKind::Other,
+        "Mumble not connected",
+    ))));
+    state.set_mumble(mumble.clone());
+    try_connect_mumble(&mumble, &state, &to_gui);
 
-    while mumble.read().unwrap().as_ref().is_err() {
+    // the GUI picks the startup source right away; start it once there's a link to feed
+    let mut requested_source = None;
+    while mumble.lock().unwrap().as_ref().is_err() {
         std::thread::sleep(std::time::Duration::from_millis(10));
         match from_gui.try_recv() {
-            Ok(FromGuiToServer::TryConnectMumble()) => {
-                try_connect_mumble(&mumble, &to_gui);
+            Ok(FromGuiToServer::TryConnectMumble())
+            | Ok(FromGuiToServer::AutoConnectMumble())
+            | Ok(FromGuiToServer::ReconnectMumble()) => {
+                try_connect_mumble(&mumble, &state, &to_gui);
             }
-            Ok(_) => {}
+            Ok(FromGuiToServer::UseSource(kind)) => requested_source = Some(kind),
+            Ok(FromGuiToServer::Shutdown()) => return,
             Err(TryRecvError::Empty) => {}
             Err(TryRecvError::Disconnected) => {
                 log::warn!("GUI channel disconnected");
@@ -153,98 +198,128 @@ pub fn server_main(
         }
     }
     log::info!("Mumble connected");
-
+    let smoothing_stop = Arc::new(AtomicBool::new(false));
+    let smoothing = spawn_smoothing_thread(mumble.clone(), state.clone(), smoothing_stop.clone());
+    let mut sources = SourceManager::new(ip_addr, port, mumble.clone(), state.clone(), to_gui.clone());
+    if let Some(kind) = requested_source {
+        sources.switch_to(kind);
+    }
     loop {
-        match from_gui.try_recv() {
-            Ok(FromGuiToServer::UseManiaPlanetTelemetry()) => {
-                let _ = run_mp_telemetry_loop(&mumble, &to_gui);
-                return;
+        match from_gui.recv() {
+            Ok(FromGuiToServer::UseSource(kind)) => {
+                sources.switch_to(kind);
             }
-            Ok(FromGuiToServer::UseSocketServer()) => {
-                break;
+            Ok(FromGuiToServer::TryConnectMumble()) => {
+                try_connect_mumble(&mumble, &state, &to_gui);
             }
-            Ok(FromGuiToServer::Shutdown()) => {
-                shutdown_tcp_server();
-                return;
+            Ok(FromGuiToServer::AutoConnectMumble()) => {
+                // already linked; Mumble reopens the existing link memory on restart
             }
-            Ok(_) => {}
-            Err(TryRecvError::Empty) => {}
-            Err(TryRecvError::Disconnected) => {
+            Ok(FromGuiToServer::ReconnectMumble()) => {
+                log::info!("Reconnecting to Mumble");
+                reconnect_mumble(&mumble, &state, &to_gui);
+            }
+            Ok(FromGuiToServer::Shutdown()) => break,
+            Err(_) => {
                 log::warn!("GUI channel disconnected");
-                return;
+                break;
             }
         }
     }
+    // tell the plugin first, and stop smoothing before the player is parked, so nothing
+    // overwrites the parked position afterwards
+    shutdown_tcp_server(&state);
+    smoothing_stop.store(true, Ordering::Relaxed);
+    if smoothing.join().is_err() {
+        log::error!("Smoothing thread panicked");
+    }
+    sources.stop();
+    log::info!("Server stopped");
+}
 
-
-
+/// Run the plugin socket server until `stop` is set and `stop_tcp_server` is called.
+pub fn run_plugin_server(
+    ip_addr: &str,
+    port: u16,
+    mumble: SharedMumble,
+    state: SharedState,
+    to_gui: Sender<ToGUI>,
+    stop: &AtomicBool,
+) {
+    let wanted_port = port;
     let (handler, listener) = node::split::<()>();
 
-    *TCP_HANDLER.write().unwrap() = Some(handler.clone());
+    state.set_plugin_handler(Some(handler.clone()));
+    // the source manager may have tried to stop us before the handler was registered
+    if stop.load(Ordering::SeqCst) {
+        handler.stop();
+    }
 
-    handler
-        .network()
-        .listen(Transport::FramedTcp, &format!("{}:{}", ip_addr, port))
-        .unwrap();
+    let fallback_ports = get_config().plugin_server.fallback_ports;
+    let Some(port) = listen_with_fallback(&handler, ip_addr, port, fallback_ports) else {
+        let _ = to_gui.send(ToGUI::PortInUse { wanted: port, actual: None });
+        handler.stop();
+        return;
+    };
     log::info!("Listening on {}:{}", ip_addr, port);
     to_gui
         .send(ToGUI::ListeningOn(ip_addr.to_string(), port))
         .unwrap();
+    if port != wanted_port {
+        let _ = to_gui.send(ToGUI::PortInUse { wanted: wanted_port, actual: Some(port) });
+    }
 
-
-
-    let player_login = Arc::new(RwLock::new(String::new()));
-    let player_name = Arc::new(RwLock::new(String::new()));
-    let server_login = Arc::new(RwLock::new(String::new()));
-    let server_team = Arc::new(RwLock::new(String::new()));
-    let update_nonce = Arc::new(RwLock::new(0));
-
+    let state = &*state;
     let update_context = |mumble: &mut MumbleLink| {
-        let ctx = format!(
-            "TM|{}|{}",
-            &server_login.read().unwrap(),
-            &server_team.read().unwrap()
-        );
-        let un = *update_nonce.read().unwrap();
-        mumble.set_identity(
-            format!(
-                "{}|{}|{}",
-                &player_name.read().unwrap(),
-                &player_login.read().unwrap(),
-                &un
-            )
-            .as_str(),
+        // the link info may hold what the telemetry source put there, like the raw map id
+        if !plugin_may_set_context(state) {
+            return;
+        }
+        let link = state.link();
+        let ctx = format!("TM|{}|{}", link.server_login, link.server_team);
+        let identity = format!(
+            "{}|{}|{}",
+            link.player_name,
+            link.player_login,
+            state.next_identity_nonce()
         );
+        mumble.set_identity(&identity);
         mumble.set_context(ctx.as_bytes());
-        *update_nonce.write().unwrap() += 1;
-        *LAST_CONTEXT.lock().unwrap() = ctx;
+        state.set_context(ctx, identity);
+    };
+    let left_server = || {
+        state.update_link(|l| {
+            l.server_login = String::new();
+            l.server_team = "All".to_string();
+        })
     };
-
-    let pl: Arc<RwLock<String>> = player_login.clone();
-    let pn = player_name.clone();
-    let sl = server_login.clone();
-    let st = server_team.clone();
 
     listener.for_each(move |event| {
         match event.network() {
             NetEvent::Message(_endpoint, data) => {
-                LAST_ENDPOINT.lock().unwrap().replace(_endpoint);
+                state.set_plugin_endpoint(Some(_endpoint));
+                state.count_plugin_message(data.len());
+                session_log::record_inbound(state, _endpoint, data);
                 // position
                 if data.len() > 0 && data[0] == 1 {
                     match read_pos_msg(&data) {
                         Ok(from_tm) => {
-                            let mut mumble_w = mumble.write().unwrap();
-                            let mumble = mumble_w.as_mut().unwrap();
-                            mumble.update(
-                                from_tm.get_pos_p().unwrap().clone().into(),
-                                from_tm.get_pos_c().unwrap().clone().into(),
-                            );
-                            if to_gui.send(ToGUI::FromTM(from_tm)).is_err() {
-                                log::warn!("GUI channel disconnected");
-                                shutdown_tcp_server();
+                            state.update_stats(|s| s.binary_positions += 1);
+                            let park = plugin_park_reason(state);
+                            if !accept_sample(state, SampleOrigin::Plugin, park.is_none()) {
+                                return;
                             }
+                            report_park_status(state, park, &to_gui);
+                            let mut mumble_w = mumble.lock().unwrap();
+                            let Ok(mumble) = mumble_w.as_mut() else {
+                                return;
+                            };
+                            let sample = plugin_sample(*from_tm.get_pos_p().unwrap(), *from_tm.get_pos_c().unwrap(), park);
+                            update_positions(mumble, state, sample);
+                            state.record_positions(sample.p.into(), sample.c.into());
                         }
                         Err(e) => {
+                            state.update_stats(|s| s.parse_errors += 1);
                             log::warn!("Error parsing position message: {}", e);
                             to_gui
                                 .send(ToGUI::ProtocolError(format!(
@@ -263,48 +338,53 @@ pub fn server_main(
                 }
                 match serde_json::from_str::<FromTM>(&json_raw) {
                     Ok(from_tm) => {
-                        let mut mumble_w = mumble.write().unwrap();
-                        let mumble = mumble_w.as_mut().unwrap();
+                        let park = match from_tm {
+                            FromTM::Positions { .. } => plugin_park_reason(state),
+                            _ => None,
+                        };
+                        let mut mumble_w = mumble.lock().unwrap();
+                        let Ok(mumble) = mumble_w.as_mut() else {
+                            return;
+                        };
                         match from_tm {
-                            m @ FromTM::Positions { p, c } => {
-                                mumble.update(p.into(), c.into());
-                                if *VISIBLE.lock().unwrap() {
-                                    to_gui.send(ToGUI::FromTM(m)).unwrap();
+                            FromTM::Positions { p, c } => {
+                                state.update_stats(|s| s.json_positions += 1);
+                                if !accept_sample(state, SampleOrigin::Plugin, park.is_none()) {
+                                    return;
                                 }
+                                report_park_status(state, park, &to_gui);
+                                let sample = plugin_sample(p, c, park);
+                                update_positions(mumble, state, sample);
+                                state.record_positions(sample.p.into(), sample.c.into());
                             }
                             ref m @ FromTM::PlayerDetails(ref name, ref login) => {
-                                *pn.write().unwrap() = name.clone();
-                                *pl.write().unwrap() = login.clone();
-                                if *VISIBLE.lock().unwrap() {
-                                    let _ = to_gui.send(ToGUI::FromTM(m.clone()));
-                                }
+                                state.update_link(|l| {
+                                    l.player_name = name.clone();
+                                    l.player_login = login.clone();
+                                });
+                                let _ = to_gui.send(ToGUI::FromTM(m.clone()));
                                 // update_context(mumble);
                             }
                             ref m @ FromTM::ServerDetails(ref name, ref team) => {
-                                *sl.write().unwrap() = name.clone();
-                                *st.write().unwrap() = team.clone();
+                                state.update_link(|l| {
+                                    l.server_login = name.clone();
+                                    l.server_team = team.clone();
+                                });
+                                set_plugin_has_server(state, true);
                                 update_context(mumble);
-                                if *VISIBLE.lock().unwrap() {
-                                    let _ = to_gui.send(ToGUI::FromTM(m.clone()));
-                                }
+                                let _ = to_gui.send(ToGUI::FromTM(m.clone()));
                             }
                             m @ FromTM::LeftServer() => {
-                                *sl.write().unwrap() = String::new();
-                                *st.write().unwrap() = "All".to_string();
+                                left_server();
+                                set_plugin_has_server(state, false);
                                 update_context(mumble);
-                                if *VISIBLE.lock().unwrap() {
-                                    let _ = to_gui.send(ToGUI::FromTM(m.clone()));
-                                }
+                                let _ = to_gui.send(ToGUI::FromTM(m.clone()));
                             }
                             m @ FromTM::Ping() => {
-                                handler.network().send(
-                                    _endpoint,
-                                    serde_json::to_string(&ToTM::Ping()).unwrap().as_bytes(),
-                                );
+                                state.update_stats(|s| s.pings += 1);
+                                send_to_tm(state, &handler, _endpoint, &ToTM::Ping());
                                 update_context(mumble);
-                                if *VISIBLE.lock().unwrap() {
-                                    let _ = to_gui.send(ToGUI::FromTM(m.clone()));
-                                }
+                                let _ = to_gui.send(ToGUI::FromTM(m.clone()));
                             }
                             FromTM::NetConnected(_, _)
                             | FromTM::NetDisconnected(_)
@@ -320,6 +400,7 @@ pub fn server_main(
                         }
                     }
                     Err(e) => {
+                        state.update_stats(|s| s.parse_errors += 1);
                         log::warn!("Error parsing message: {}", e);
                         to_gui
                             .send(ToGUI::ProtocolError(format!(
@@ -336,15 +417,16 @@ pub fn server_main(
                 // to_gui
                 //     .send(FromTM::NetConnected(_endpoint.addr(), connection_success).into())
                 //     .unwrap();
-                // handler.network().send(_endpoint, serde_json::to_string(&ToTM::ConnectedStatus(mumble.read().unwrap().as_ref().is_ok())).unwrap().as_bytes());
+                // handler.network().send(_endpoint, serde_json::to_string(&ToTM::ConnectedStatus(mumble.lock().unwrap().as_ref().is_ok())).unwrap().as_bytes());
             }
             NetEvent::Disconnected(_endpoint) => {
-                let mut mumble_w = mumble.write().unwrap();
-                let mumble = mumble_w.as_mut().unwrap();
                 log::info!("Client disconnected");
-                *sl.write().unwrap() = String::new();
-                *st.write().unwrap() = "All".to_string();
-                update_context(mumble);
+                set_plugin_connected(state, false);
+                session_log::record_disconnected(state, _endpoint);
+                left_server();
+                if let Ok(mumble) = mumble.lock().unwrap().as_mut() {
+                    update_context(mumble);
+                }
                 let r: Result<_, SendError<_>> = (|| {
                     to_gui.send(FromTM::LeftServer().into())?;
                     to_gui.send(FromTM::NetDisconnected(_endpoint.addr()).into())?;
@@ -354,60 +436,113 @@ pub fn server_main(
                     Ok(_) => {}
                     Err(_) => {
                         log::warn!("GUI channel disconnected");
-                        shutdown_tcp_server();
+                        shutdown_tcp_server(state);
                     }
                 }
             }
             NetEvent::Accepted(_endpoint, _listener) => {
                 log::info!("Client accepted");
+                set_plugin_connected(state, true);
+                session_log::record_accepted(state, _endpoint);
                 to_gui
                     .send(FromTM::NetAccepted(_endpoint.addr()).into())
                     .unwrap();
-                handler.network().send(
+                send_to_tm(
+                    state,
+                    &handler,
                     _endpoint,
-                    serde_json::to_string(&ToTM::LinkAppInfo {
+                    &ToTM::LinkAppInfo {
                         version: env!("CARGO_PKG_VERSION").to_string(),
                         options: vec![],
-                    })
-                    .unwrap()
-                    .as_bytes(),
+                    },
                 );
-                handler.network().send(
+                send_to_tm(
+                    state,
+                    &handler,
                     _endpoint,
-                    serde_json::to_string(&ToTM::ConnectedStatus(
-                        mumble.read().unwrap().as_ref().is_ok(),
-                    ))
-                    .unwrap()
-                    .as_bytes(),
+                    &ToTM::ConnectedStatus(mumble.lock().unwrap().as_ref().is_ok()),
                 );
             }
         }
     });
 }
 
-fn try_connect_mumble(mumble: &Arc<RwLock<std::io::Result<MumbleLink>>>, to_gui: &Sender<ToGUI>) {
-    let mut mumble_w = mumble.write().unwrap();
-    match mumble_w.as_ref() {
-        Ok(_) => {
-            log::warn!("Mumble already connected");
-            to_gui
-                .send(ToGUI::MumbleError("Mumble already connected".to_string()))
-                .unwrap();
+/// Listen on `port`, or the first free one of the `fallbacks` ports after it.
+/// Another copy of the bridge is caught by the single instance check before we get here,
+/// so a taken port belongs to some other program.
+fn listen_with_fallback(
+    handler: &node::NodeHandler<()>,
+    ip_addr: &str,
+    port: u16,
+    fallbacks: u16,
+) -> Option<u16> {
+    for candidate in (port..=port.saturating_add(fallbacks)).filter(|p| *p != 0) {
+        match handler
+            .network()
+            .listen(Transport::FramedTcp, &format!("{}:{}", ip_addr, candidate))
+        {
+            Ok(_) => return Some(candidate),
+            Err(e) => log::warn!("Can't listen on {}:{}: {}", ip_addr, candidate, e),
         }
-        Err(_) => {}
     }
-    *mumble_w = MumbleLink::new(
+    log::error!("No free port in {}..={}", port, port.saturating_add(fallbacks));
+    None
+}
+
+fn send_to_tm(state: &BridgeState, handler: &node::NodeHandler<()>, endpoint: Endpoint, msg: &ToTM) {
+    session_log::record_outbound(state, endpoint, msg);
+    handler
+        .network()
+        .send(endpoint, serde_json::to_string(msg).unwrap().as_bytes());
+}
+
+fn try_connect_mumble(mumble: &SharedMumble, state: &BridgeState, to_gui: &Sender<ToGUI>) {
+    let mut mumble_w = mumble.lock().unwrap();
+    if mumble_w.is_ok() {
+        log::warn!("Mumble already connected");
+        let _ = to_gui.send(ToGUI::MumbleError("Mumble already connected".to_string()));
+        return;
+    }
+    match MumbleLink::new(
         "TM-Proximity-Chat",
         "Bridge to TM2020 plugin for proximity chat",
-    );
-    drop(mumble_w);
+    ) {
+        Ok(link) => {
+            *mumble_w = Ok(link);
+            drop(mumble_w);
+            state.update_link(|l| l.mumble_connected = true);
+            let _ = to_gui.send(ToGUI::IsConnected(true));
+        }
+        Err(e) => {
+            drop(mumble_w);
+            state.update_link(|l| l.mumble_connected = false);
+            let _ = to_gui.send(ToGUI::IsConnected(false));
+            let _ = to_gui.send(ToGUI::MumbleError(e.to_string()));
+        }
+    }
+}
 
-    let mumble_r = mumble.read().unwrap();
-    to_gui.send(ToGUI::IsConnected(mumble_r.is_ok())).unwrap();
-    if let Err(e) = mumble_r.as_ref() {
-        to_gui.send(ToGUI::MumbleError(e.to_string())).unwrap();
+/// Replace the link with a new one, keeping the old link if Mumble can't be reached.
+fn reconnect_mumble(mumble: &SharedMumble, state: &BridgeState, to_gui: &Sender<ToGUI>) {
+    match MumbleLink::new(
+        "TM-Proximity-Chat",
+        "Bridge to TM2020 plugin for proximity chat",
+    ) {
+        Ok(mut link) => {
+            // dropping the old link clears the shared memory, so Mumble sees it go away;
+            // the new one starts over with the current identity and context
+            let current = state.link();
+            link.set_identity(&current.identity);
+            link.set_context(current.context.as_bytes());
+            *mumble.lock().unwrap() = Ok(link);
+            state.update_link(|l| l.mumble_connected = true);
+            let _ = to_gui.send(ToGUI::IsConnected(true));
+        }
+        Err(e) => {
+            log::warn!("Failed to reconnect to Mumble, keeping the old link: {}", e);
+            let _ = to_gui.send(ToGUI::MumbleError(format!("Failed to reconnect to Mumble: {}", e)));
+        }
     }
-    drop(mumble_r);
 }
 
 fn read_vec3(r: &mut std::io::Cursor<&[u8]>) -> Result<[f32; 3], std::io::Error> {
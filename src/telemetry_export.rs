@@ -0,0 +1,297 @@
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use serde::Serialize;
+
+use crate::bridge_state::BridgeState;
+use crate::mp_telemetry_data::{Quat, RaceState, Vec3};
+use crate::telemetry_frame::TelemetryFrame;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Csv,
+    JsonLines,
+}
+
+impl ExportFormat {
+    /// `.csv` is CSV, anything else JSON lines.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("csv") => ExportFormat::Csv,
+            _ => ExportFormat::JsonLines,
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "csv" => Some(ExportFormat::Csv),
+            "jsonl" | "json" => Some(ExportFormat::JsonLines),
+            _ => None,
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::JsonLines => "jsonl",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExportOptions {
+    pub path: PathBuf,
+    pub format: ExportFormat,
+    /// Start a new file (`<name>-run001.csv`, ...) whenever a new race run starts.
+    pub split_per_run: bool,
+}
+
+const CSV_HEADER: &str = "t_ms,update_number,race_state,race_time_ms,x,y,z,vx,vy,vz,qw,qx,qy,qz,speed_kmh,gear,nb_checkpoints,checkpoint_times";
+
+/// Writes one row per telemetry update to CSV or JSON lines.
+pub struct TelemetryExporter {
+    opts: ExportOptions,
+    out: Option<BufWriter<File>>,
+    current_path: Option<PathBuf>,
+    start: Instant,
+    run: u32,
+    last_update_nb: Option<u32>,
+    last_race_state: Option<RaceState>,
+    /// Whether the current file has frames from a running race, i.e. is worth keeping separate.
+    run_has_frames: bool,
+}
+
+impl TelemetryExporter {
+    pub fn new(opts: ExportOptions) -> Self {
+        TelemetryExporter {
+            opts,
+            out: None,
+            current_path: None,
+            start: Instant::now(),
+            run: 0,
+            last_update_nb: None,
+            last_race_state: None,
+            run_has_frames: false,
+        }
+    }
+
+    fn file_path(&self) -> PathBuf {
+        if !self.opts.split_per_run {
+            return self.opts.path.clone();
+        }
+        let stem = self.opts.path.file_stem().unwrap_or_default().to_string_lossy();
+        let name = format!("{}-run{:03}.{}", stem, self.run, self.opts.format.extension());
+        self.opts.path.with_file_name(name)
+    }
+
+    fn open_next(&mut self) -> io::Result<()> {
+        self.finish()?;
+        self.run += 1;
+        let path = self.file_path();
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+        let mut out = BufWriter::new(File::create(&path)?);
+        if self.opts.format == ExportFormat::Csv {
+            writeln!(out, "{}", CSV_HEADER)?;
+        }
+        log::info!("Exporting telemetry to {}", path.display());
+        self.out = Some(out);
+        self.current_path = Some(path);
+        self.run_has_frames = false;
+        Ok(())
+    }
+
+    /// A run starts with the countdown, or directly with `Running` if we missed the countdown.
+    fn is_new_run(&self, state: RaceState) -> bool {
+        match (self.last_race_state, state) {
+            (Some(prev), _) if prev == state => false,
+            (_, RaceState::BeforeStart) => true,
+            (Some(RaceState::BeforeStart), RaceState::Running) => false,
+            (_, RaceState::Running) => true,
+            _ => false,
+        }
+    }
+
+    pub fn write_frame(&mut self, t: &TelemetryFrame) -> io::Result<()> {
+        if self.last_update_nb == Some(t.update_number) {
+            return Ok(());
+        }
+        self.last_update_nb = Some(t.update_number);
+
+        let state = t.race.state;
+        let split = self.opts.split_per_run && self.is_new_run(state) && self.run_has_frames;
+        let state_changed = self.last_race_state != Some(state);
+        self.last_race_state = Some(state);
+        match self.out.as_mut() {
+            Some(_) if split => self.open_next()?,
+            None => self.open_next()?,
+            // so a finished run is on disk even if we're killed later
+            Some(out) if state_changed => out.flush()?,
+            Some(_) => {}
+        }
+        if state == RaceState::Running {
+            self.run_has_frames = true;
+        }
+
+        let t_ms = self.start.elapsed().as_millis() as u64;
+        let out = self.out.as_mut().unwrap();
+        match self.opts.format {
+            ExportFormat::Csv => write_csv_row(out, t_ms, t),
+            ExportFormat::JsonLines => {
+                serde_json::to_writer(&mut *out, &ExportRow::new(t_ms, t))?;
+                out.write_all(b"\n")
+            }
+        }
+    }
+
+    pub fn current_path(&self) -> Option<&Path> {
+        self.current_path.as_deref()
+    }
+
+    pub fn finish(&mut self) -> io::Result<()> {
+        match self.out.take() {
+            Some(mut out) => out.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+fn write_csv_row(out: &mut impl Write, t_ms: u64, t: &TelemetryFrame) -> io::Result<()> {
+    let o = &t.object;
+    let (p, v, q) = (o.position, o.velocity, o.rotation);
+    let cps: Vec<String> = t.race.checkpoint_times.iter().map(|c| c.to_string()).collect();
+    writeln!(
+        out,
+        "{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
+        t_ms,
+        t.update_number,
+        t.race.state,
+        t.race.time_ms,
+        p.x,
+        p.y,
+        p.z,
+        v.x,
+        v.y,
+        v.z,
+        q.w,
+        q.x,
+        q.y,
+        q.z,
+        t.vehicle.speed_kmh,
+        t.vehicle.engine_cur_gear,
+        t.race.checkpoint_times.len(),
+        cps.join(";"),
+    )
+}
+
+/// One JSON line; the same fields as the CSV columns.
+#[derive(Serialize)]
+struct ExportRow<'a> {
+    t_ms: u64,
+    update_number: u32,
+    race_state: RaceState,
+    race_time_ms: u32,
+    position: Vec3,
+    velocity: Vec3,
+    rotation: Quat,
+    speed_kmh: u32,
+    gear: i32,
+    checkpoint_times: &'a [u32],
+}
+
+impl<'a> ExportRow<'a> {
+    fn new(t_ms: u64, t: &'a TelemetryFrame) -> Self {
+        ExportRow {
+            t_ms,
+            update_number: t.update_number,
+            race_state: t.race.state,
+            race_time_ms: t.race.time_ms,
+            position: t.object.position,
+            velocity: t.object.velocity,
+            rotation: t.object.rotation,
+            speed_kmh: t.vehicle.speed_kmh,
+            gear: t.vehicle.engine_cur_gear,
+            checkpoint_times: &t.race.checkpoint_times,
+        }
+    }
+}
+
+/// Start exporting telemetry frames, replacing any running export. Files are created lazily on
+/// the first frame.
+pub fn start_export(state: &BridgeState, opts: ExportOptions) {
+    stop_export(state);
+    log::info!("Starting telemetry export ({:?}) to {}", opts.format, opts.path.display());
+    *state.telemetry_exporter() = Some(TelemetryExporter::new(opts));
+}
+
+pub fn stop_export(state: &BridgeState) {
+    if let Some(mut exporter) = state.telemetry_exporter().take() {
+        if let Err(e) = exporter.finish() {
+            log::error!("Failed to flush telemetry export: {}", e);
+        }
+        log::info!("Stopped telemetry export");
+    }
+}
+
+pub fn is_exporting(state: &BridgeState) -> bool {
+    state.telemetry_exporter().is_some()
+}
+
+/// The file currently being written, if any.
+pub fn export_file(state: &BridgeState) -> Option<PathBuf> {
+    state
+        .telemetry_exporter()
+        .as_ref()
+        .and_then(|e| e.current_path().map(Path::to_path_buf))
+}
+
+/// Called by the telemetry loop for every frame read; repeated frames are skipped.
+pub fn export_frame(state: &BridgeState, t: &TelemetryFrame) {
+    let mut exporter = state.telemetry_exporter();
+    if let Some(e) = exporter.as_mut() {
+        if let Err(err) = e.write_frame(t) {
+            log::error!("Failed to write telemetry export, stopping export: {}", err);
+            *exporter = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Which of `states` start a new run, tracking state the way `write_frame` does.
+    fn new_runs(states: &[RaceState]) -> Vec<bool> {
+        let mut exporter = TelemetryExporter::new(ExportOptions {
+            path: PathBuf::from("unused.csv"),
+            format: ExportFormat::Csv,
+            split_per_run: true,
+        });
+        states
+            .iter()
+            .map(|&state| {
+                let new_run = exporter.is_new_run(state);
+                exporter.last_race_state = Some(state);
+                new_run
+            })
+            .collect()
+    }
+
+    #[test]
+    fn detects_new_runs() {
+        use RaceState::*;
+        // countdown, race, finish, then straight back into a run without a countdown (missed it)
+        assert_eq!(
+            new_runs(&[BeforeStart, Running, Running, Finished, Running]),
+            [true, false, false, false, true]
+        );
+        // a checkpoint respawn keeps `Running`; respawning at the start goes through the countdown
+        assert_eq!(
+            new_runs(&[Running, Running, BeforeStart, BeforeStart, Running, Finished]),
+            [true, false, true, false, false, false]
+        );
+    }
+}
@@ -0,0 +1,188 @@
+use serde::{Deserialize, Serialize};
+
+use crate::mp_telemetry_data::{GameState, Handicap, Quat, RaceState, STelemetry, Vec3};
+
+/// An owned, typed copy of one telemetry frame: strings instead of C strings, bools instead of
+/// `Bool`s, enums for states, and only the checkpoints passed so far.
+/// This is what the rest of the app works with; `STelemetry` is only the shared memory layout.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TelemetryFrame {
+    pub version: u32,
+    pub update_number: u32,
+    pub game: GameInfo,
+    pub race: RaceInfo,
+    pub object: ObjectInfo,
+    pub vehicle: VehicleInfo,
+    pub device: DeviceInfo,
+    pub player: PlayerInfo,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GameInfo {
+    pub state: GameState,
+    /// Player model, e.g. `CarSport`.
+    pub gameplay_variant: String,
+    pub map_id: String,
+    pub map_name: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RaceInfo {
+    pub state: RaceState,
+    pub time_ms: u32,
+    pub nb_respawns: u32,
+    pub checkpoint_times: Vec<u32>,
+    pub nb_checkpoints_per_lap: u32,
+    pub nb_laps_per_race: u32,
+    pub timestamp: u32,
+    pub start_timestamp: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ObjectInfo {
+    pub timestamp: u32,
+    /// Changes every time the car is teleported.
+    pub discontinuity_count: u32,
+    pub rotation: Quat,
+    /// +x is left, +y is up, +z is front.
+    pub position: Vec3,
+    pub velocity: Vec3,
+    pub latest_stable_ground_contact_time: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct WheelInfo {
+    pub ground_contact: bool,
+    pub slipping: bool,
+    pub damper_len: f32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VehicleInfo {
+    pub timestamp: u32,
+    pub input_steer: f32,
+    pub input_gas_pedal: f32,
+    pub input_is_braking: bool,
+    pub input_is_horn: bool,
+    pub engine_rpm: f32,
+    pub engine_cur_gear: i32,
+    pub engine_turbo_ratio: f32,
+    pub engine_freewheeling: bool,
+    /// FL, FR, RR, RL.
+    pub wheels: [WheelInfo; 4],
+    pub wheels_damper_range_min: f32,
+    pub wheels_damper_range_max: f32,
+    pub rumble_intensity: f32,
+    pub speed_kmh: u32,
+    pub is_in_water: bool,
+    pub is_sparkling: bool,
+    pub is_light_trails: bool,
+    pub is_lights_on: bool,
+    pub is_flying: bool,
+    pub is_on_ice: bool,
+    pub handicap: Handicap,
+    pub boost_ratio: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct DeviceInfo {
+    /// yaw, pitch, roll
+    pub euler: Vec3,
+    pub centered_yaw: f32,
+    pub centered_altitude: f32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlayerInfo {
+    /// False when spectating another player or watching a replay.
+    pub is_local_player: bool,
+    pub trigram: String,
+    pub dossard_number: String,
+    pub hue: f32,
+    pub user_name: String,
+}
+
+impl From<&STelemetry> for TelemetryFrame {
+    fn from(t: &STelemetry) -> Self {
+        let v = &t.vehicle;
+        let contact = v.wheels_is_ground_contact();
+        let slipping = v.wheels_is_slipping();
+        TelemetryFrame {
+            version: t.header.version,
+            update_number: t.update_number,
+            game: GameInfo {
+                state: t.game.game_state(),
+                gameplay_variant: t.game.gameplay_variant(),
+                map_id: t.game.map_id(),
+                map_name: t.game.map_name(),
+            },
+            race: RaceInfo {
+                state: t.race.race_state(),
+                time_ms: t.race.time,
+                nb_respawns: t.race.nb_respawns,
+                checkpoint_times: t.race.checkpoint_times().to_vec(),
+                nb_checkpoints_per_lap: t.race.nb_checkpoints_per_lap,
+                nb_laps_per_race: t.race.nb_laps_per_race,
+                timestamp: t.race.timestamp,
+                start_timestamp: t.race.start_timestamp,
+            },
+            object: ObjectInfo {
+                timestamp: t.object.timestamp,
+                discontinuity_count: t.object.discontinuity_count,
+                rotation: t.object.rotation,
+                position: t.object.translation,
+                velocity: t.object.velocity,
+                latest_stable_ground_contact_time: t.object.latest_stable_ground_contact_time,
+            },
+            vehicle: VehicleInfo {
+                timestamp: v.timestamp,
+                input_steer: v.input_steer,
+                input_gas_pedal: v.input_gas_pedal,
+                input_is_braking: v.input_is_braking(),
+                input_is_horn: v.input_is_horn(),
+                engine_rpm: v.engine_rpm,
+                engine_cur_gear: v.engine_cur_gear,
+                engine_turbo_ratio: v.engine_turbo_ratio,
+                engine_freewheeling: v.engine_freewheeling(),
+                wheels: std::array::from_fn(|i| WheelInfo {
+                    ground_contact: contact[i],
+                    slipping: slipping[i],
+                    damper_len: v.wheels_damper_len[i],
+                }),
+                wheels_damper_range_min: v.wheels_damper_range_min,
+                wheels_damper_range_max: v.wheels_damper_range_max,
+                rumble_intensity: v.rumble_intensity,
+                speed_kmh: v.speed_meter,
+                is_in_water: v.is_in_water(),
+                is_sparkling: v.is_sparkling(),
+                is_light_trails: v.is_light_trails(),
+                is_lights_on: v.is_lights_on(),
+                is_flying: v.is_flying(),
+                is_on_ice: v.is_on_ice(),
+                handicap: v.handicap(),
+                boost_ratio: v.boost_ratio,
+            },
+            device: DeviceInfo {
+                euler: t.device.euler,
+                centered_yaw: t.device.centered_yaw,
+                centered_altitude: t.device.centered_altitude,
+            },
+            player: PlayerInfo {
+                is_local_player: t.player.is_local_player(),
+                trigram: t.player.trigram(),
+                dossard_number: t.player.dossard_number(),
+                hue: t.player.hue,
+                user_name: t.player.user_name(),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+impl TelemetryFrame {
+    /// The checked-in synthetic snapshot, for tests that need a whole frame.
+    pub fn fixture() -> Self {
+        let bytes = include_bytes!("../testdata/telemetry/synthetic-fixture.bin");
+        TelemetryFrame::from(&STelemetry::from_bytes(bytes).unwrap())
+    }
+}
@@ -0,0 +1,316 @@
+use egui::{Color32, ProgressBar, Sense};
+use egui_extras::{Column, TableBuilder};
+
+use crate::app::fmt_vec3;
+use crate::telemetry_frame::TelemetryFrame;
+use crate::race_events::fmt_race_time;
+
+/// Top speed shown on the speed gauge (km/h).
+const SPEED_GAUGE_MAX: f32 = 1000.0;
+/// `EngineRpm` goes from 1500 to 10000.
+const RPM_GAUGE_MAX: f32 = 10000.0;
+const ROW_HEIGHT: f32 = 16.0;
+
+/// The telemetry panel: collapsible sections with tables and gauges, plus freeze / copy as JSON.
+#[derive(Default)]
+pub struct TelemetryView {
+    frozen: Option<TelemetryFrame>,
+}
+
+impl TelemetryView {
+    pub fn ui(&mut self, ui: &mut egui::Ui, live: &TelemetryFrame) {
+        ui.horizontal(|ui| {
+            ui.strong("Telemetry");
+            let freeze_label = if self.frozen.is_some() {
+                "Unfreeze"
+            } else {
+                "Freeze"
+            };
+            if ui.button(freeze_label).clicked() {
+                self.frozen = match self.frozen {
+                    Some(_) => None,
+                    None => Some(live.clone()),
+                };
+            }
+            let t = self.frozen.as_ref().unwrap_or(live);
+            if ui.button("Copy as JSON").clicked() {
+                match serde_json::to_string_pretty(t) {
+                    Ok(s) => ui.ctx().copy_text(s),
+                    Err(e) => log::error!("Failed to serialize telemetry: {}", e),
+                }
+            }
+            if self.frozen.is_some() {
+                ui.label(format!("(frozen at update {})", t.update_number));
+            }
+        });
+        let t = self.frozen.as_ref().unwrap_or(live);
+
+        egui::CollapsingHeader::new("Overview")
+            .id_salt("tv_overview")
+            .default_open(true)
+            .show(ui, |ui| ui_overview(ui, t));
+        egui::CollapsingHeader::new("Race")
+            .id_salt("tv_race")
+            .default_open(true)
+            .show(ui, |ui| ui_race(ui, t));
+        egui::CollapsingHeader::new("Vehicle")
+            .id_salt("tv_vehicle")
+            .default_open(true)
+            .show(ui, |ui| ui_vehicle(ui, t));
+        egui::CollapsingHeader::new("Object")
+            .id_salt("tv_object")
+            .show(ui, |ui| ui_object(ui, t));
+        egui::CollapsingHeader::new("Device")
+            .id_salt("tv_device")
+            .show(ui, |ui| ui_device(ui, t));
+    }
+}
+
+fn ui_overview(ui: &mut egui::Ui, t: &TelemetryFrame) {
+    let handicap = t.vehicle.handicap;
+    kv_table(
+        ui,
+        "tv_overview_table",
+        &[
+            ("Game state", t.game.state.to_string()),
+            ("Gameplay", t.game.gameplay_variant.clone()),
+            ("Map", t.game.map_name.clone()),
+            ("Map UID", t.game.map_id.clone()),
+            ("Player", t.player.user_name.clone()),
+            (
+                "Trigram / dossard",
+                format!("{} / {}", t.player.trigram, t.player.dossard_number),
+            ),
+            ("Local player", t.player.is_local_player.to_string()),
+            (
+                "Handicap",
+                if handicap.is_empty() {
+                    "None".into()
+                } else {
+                    format!("{:?}", handicap)
+                },
+            ),
+            (
+                "Version / update",
+                format!("{} / {}", t.version, t.update_number),
+            ),
+        ],
+    );
+}
+
+fn ui_race(ui: &mut egui::Ui, t: &TelemetryFrame) {
+    let race = &t.race;
+    kv_table(
+        ui,
+        "tv_race_table",
+        &[
+            ("State", race.state.to_string()),
+            ("Time", fmt_race_time(race.time_ms)),
+            ("Respawns", race.nb_respawns.to_string()),
+            ("Checkpoints", race.checkpoint_times.len().to_string()),
+            ("CPs per lap", race.nb_checkpoints_per_lap.to_string()),
+            ("Laps", race.nb_laps_per_race.to_string()),
+        ],
+    );
+
+    let cps = &race.checkpoint_times;
+    if cps.is_empty() {
+        return;
+    }
+    ui.add_space(4.0);
+    ui.push_id("tv_checkpoints", |ui| {
+        TableBuilder::new(ui)
+            .striped(true)
+            .column(Column::auto().at_least(30.0))
+            .column(Column::auto().at_least(70.0))
+            .column(Column::remainder())
+            .max_scroll_height(150.0)
+            .header(ROW_HEIGHT, |mut header| {
+                header.col(|ui| {
+                    ui.strong("CP");
+                });
+                header.col(|ui| {
+                    ui.strong("Time");
+                });
+                header.col(|ui| {
+                    ui.strong("Split");
+                });
+            })
+            .body(|body| {
+                body.rows(ROW_HEIGHT, cps.len(), |mut row| {
+                    let i = row.index();
+                    let prev = if i == 0 { 0 } else { cps[i - 1] };
+                    row.col(|ui| {
+                        ui.label((i + 1).to_string());
+                    });
+                    row.col(|ui| {
+                        ui.label(fmt_race_time(cps[i]));
+                    });
+                    row.col(|ui| {
+                        ui.label(fmt_race_time(cps[i].saturating_sub(prev)));
+                    });
+                });
+            });
+    });
+}
+
+fn ui_vehicle(ui: &mut egui::Ui, t: &TelemetryFrame) {
+    let v = &t.vehicle;
+    ui.horizontal(|ui| {
+        ui.label(format!("Gear {}", v.engine_cur_gear));
+        ui.add(
+            ProgressBar::new(v.speed_kmh as f32 / SPEED_GAUGE_MAX)
+                .desired_width(140.0)
+                .text(format!("{} km/h", v.speed_kmh)),
+        );
+        ui.add(
+            ProgressBar::new(v.engine_rpm / RPM_GAUGE_MAX)
+                .desired_width(140.0)
+                .text(format!("{:.0} rpm", v.engine_rpm)),
+        );
+    });
+    ui.horizontal(|ui| {
+        ui.add(
+            ProgressBar::new(v.input_gas_pedal)
+                .desired_width(100.0)
+                .text(format!("Gas {:.0}%", v.input_gas_pedal * 100.0)),
+        );
+        // steer is -1 (left) .. 1 (right)
+        ui.add(
+            ProgressBar::new((v.input_steer + 1.0) / 2.0)
+                .desired_width(100.0)
+                .text(format!("Steer {:+.2}", v.input_steer)),
+        );
+        ui.label(if v.input_is_braking {
+            "Brake: ✅"
+        } else {
+            "Brake: ❌"
+        });
+    });
+    if v.engine_turbo_ratio > 0.0 || v.boost_ratio > 0.0 {
+        ui.horizontal(|ui| {
+            ui.add(
+                ProgressBar::new(v.engine_turbo_ratio)
+                    .desired_width(100.0)
+                    .text(format!("Turbo {:.0}%", v.engine_turbo_ratio * 100.0)),
+            );
+            ui.add(
+                ProgressBar::new(v.boost_ratio)
+                    .desired_width(100.0)
+                    .text(format!("Boost {:.0}%", v.boost_ratio * 100.0)),
+            );
+        });
+    }
+
+    ui.horizontal(|ui| {
+        ui_wheels(ui, t);
+        ui.vertical(|ui| {
+            let flags = [
+                ("Flying", v.is_flying),
+                ("On ice", v.is_on_ice),
+                ("In water", v.is_in_water),
+                ("Sparkling", v.is_sparkling),
+                ("Free wheeling", v.engine_freewheeling),
+                ("Lights on", v.is_lights_on),
+                ("Light trails", v.is_light_trails),
+            ];
+            let active: Vec<_> = flags
+                .iter()
+                .filter(|(_, on)| *on)
+                .map(|(name, _)| *name)
+                .collect();
+            ui.label(if active.is_empty() {
+                "-".to_string()
+            } else {
+                active.join(", ")
+            });
+            ui.label(format!("Rumble: {:.2}", v.rumble_intensity));
+        });
+    });
+}
+
+/// Wheels drawn as a 2x2 grid seen from above, front on top.
+/// Green: ground contact, orange: contact + slipping, grey: in the air.
+fn ui_wheels(ui: &mut egui::Ui, t: &TelemetryFrame) {
+    let wheels = &t.vehicle.wheels;
+    // FL, FR, RR, RL
+    let layout = [[0, 1], [3, 2]];
+    let r = 6.0;
+    let (rect, resp) =
+        ui.allocate_exact_size(egui::vec2(4.0 * r + 12.0, 4.0 * r + 12.0), Sense::hover());
+    let painter = ui.painter_at(rect);
+    for (row, row_wheels) in layout.iter().enumerate() {
+        for (col, &w) in row_wheels.iter().enumerate() {
+            let center = rect.left_top()
+                + egui::vec2(
+                    r + 3.0 + col as f32 * (2.0 * r + 6.0),
+                    r + 3.0 + row as f32 * (2.0 * r + 6.0),
+                );
+            let color = match (wheels[w].ground_contact, wheels[w].slipping) {
+                (true, true) => Color32::from_rgb(240, 150, 30),
+                (true, false) => Color32::from_rgb(60, 200, 80),
+                (false, _) => Color32::GRAY,
+            };
+            painter.circle_filled(center, r, color);
+        }
+    }
+    let damper_len = wheels.map(|w| w.damper_len);
+    resp.on_hover_text(format!("Damper length: {:.3?}", damper_len));
+}
+
+fn ui_object(ui: &mut egui::Ui, t: &TelemetryFrame) {
+    let o = &t.object;
+    let rot = o.rotation;
+    kv_table(
+        ui,
+        "tv_object_table",
+        &[
+            ("Position", fmt_vec3(o.position.into())),
+            ("Velocity", fmt_vec3(o.velocity.into())),
+            (
+                "Rotation",
+                format!("<{:.3}, {:.3}, {:.3}, {:.3}>", rot.w, rot.x, rot.y, rot.z),
+            ),
+            ("Timestamp", o.timestamp.to_string()),
+            ("Discontinuities", o.discontinuity_count.to_string()),
+            (
+                "Last stable contact",
+                o.latest_stable_ground_contact_time.to_string(),
+            ),
+        ],
+    );
+}
+
+fn ui_device(ui: &mut egui::Ui, t: &TelemetryFrame) {
+    let d = &t.device;
+    kv_table(
+        ui,
+        "tv_device_table",
+        &[
+            ("Euler", fmt_vec3(d.euler.into())),
+            ("Centered yaw", format!("{:.3}", d.centered_yaw)),
+            ("Centered altitude", format!("{:.3}", d.centered_altitude)),
+        ],
+    );
+}
+
+fn kv_table(ui: &mut egui::Ui, id: &str, rows: &[(&str, String)]) {
+    ui.push_id(id, |ui| {
+        TableBuilder::new(ui)
+            .striped(true)
+            .column(Column::auto().at_least(110.0))
+            .column(Column::remainder())
+            .vscroll(false)
+            .body(|body| {
+                body.rows(ROW_HEIGHT, rows.len(), |mut row| {
+                    let (label, val) = &rows[row.index()];
+                    row.col(|ui| {
+                        ui.label(*label);
+                    });
+                    row.col(|ui| {
+                        ui.label(val);
+                    });
+                });
+            });
+    });
+}
@@ -0,0 +1,283 @@
+use std::sync::mpsc::Sender;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use egui::Context;
+use tray_icon::{
+    menu::{CheckMenuItem, Menu, MenuEvent, MenuItem, PredefinedMenuItem},
+    Icon, MouseButton, MouseButtonState, TrayIcon, TrayIconBuilder, TrayIconEvent,
+};
+
+use crate::app::FromGuiToServer;
+use crate::bridge_state::{BridgeState, SharedState};
+use crate::source_manager::SourceKind;
+use crate::shutdown::ShutdownTrigger;
+use crate::{set_window_visible, ICON_DATA};
+
+const MID_SHOW: &str = "1";
+const MID_HIDE: &str = "2";
+const MID_EXIT: &str = "3";
+const MID_SOURCE_PLUGIN: &str = "4";
+const MID_SOURCE_TELEMETRY: &str = "5";
+const MID_SOURCE_HYBRID: &str = "6";
+const MID_RECONNECT_MUMBLE: &str = "7";
+const MID_PROXIMITY: &str = "8";
+
+const REFRESH_INTERVAL: Duration = Duration::from_millis(500);
+/// Longer contexts are cut off in the menu.
+const MAX_CONTEXT_LEN: usize = 48;
+/// Windows truncates tooltips longer than this.
+const MAX_TOOLTIP_LEN: usize = 127;
+
+/// Overall state of the bridge, shown as the tray icon.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkState {
+    Disconnected,
+    /// Linked to Mumble, but no live positions from the game.
+    MumbleOnly,
+    /// Positions are flowing from the game to Mumble.
+    Linked,
+    Error,
+}
+
+impl LinkState {
+    pub fn label(&self) -> &'static str {
+        match self {
+            LinkState::Disconnected => "disconnected",
+            LinkState::MumbleOnly => "Mumble only",
+            LinkState::Linked => "live",
+            LinkState::Error => "error",
+        }
+    }
+
+    fn badge_color(&self) -> [u8; 3] {
+        match self {
+            LinkState::Disconnected => [140, 140, 140],
+            LinkState::MumbleOnly => [240, 170, 30],
+            LinkState::Linked => [40, 200, 70],
+            LinkState::Error => [220, 40, 40],
+        }
+    }
+}
+
+/// What the tray shows about the bridge; built by the GUI.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrayStatus {
+    pub link: LinkState,
+    pub mumble_linked: bool,
+    pub tm: String,
+    pub context: String,
+    pub player: String,
+    pub server: String,
+    pub team: String,
+    pub since_update: Duration,
+}
+
+impl TrayStatus {
+    fn tooltip(&self) -> String {
+        let mut tip = format!("TM to Mumble Link: {}", self.link.label());
+        if !self.player.is_empty() {
+            tip += &format!("\nPlayer: {}", self.player);
+        }
+        if !self.server.is_empty() {
+            tip += &format!("\nServer: {} ({})", self.server, self.team);
+        }
+        if !self.context.is_empty() {
+            tip += &format!("\nContext: {}", self.context);
+        }
+        if self.link != LinkState::Disconnected {
+            tip += &format!("\nLast update: {}s ago", self.since_update.as_secs());
+        }
+        truncate(&tip, MAX_TOOLTIP_LEN)
+    }
+}
+
+fn truncate(s: &str, max_chars: usize) -> String {
+    if s.chars().count() <= max_chars {
+        return s.to_string();
+    }
+    let mut out: String = s.chars().take(max_chars - 1).collect();
+    out.push('…');
+    out
+}
+
+/// `ICON_DATA` with a status badge in the bottom right corner; greyed out when disconnected.
+fn status_icon(link: LinkState) -> Icon {
+    let (mut rgba, w, h) = ICON_DATA.clone();
+    if link == LinkState::Disconnected {
+        for px in rgba.chunks_exact_mut(4) {
+            let grey = ((px[0] as u32 * 3 + px[1] as u32 * 6 + px[2] as u32) / 10) as u8;
+            px[0] = grey;
+            px[1] = grey;
+            px[2] = grey;
+        }
+    }
+    let radius = w.min(h) as f32 * 0.2;
+    let outline = (radius * 0.25).max(1.0);
+    let (cx, cy) = (w as f32 - radius - outline, h as f32 - radius - outline);
+    let color = link.badge_color();
+    for y in 0..h {
+        for x in 0..w {
+            let d = ((x as f32 + 0.5 - cx).powi(2) + (y as f32 + 0.5 - cy).powi(2)).sqrt();
+            let i = (y * w + x) as usize * 4;
+            if d <= radius {
+                rgba[i..i + 4].copy_from_slice(&[color[0], color[1], color[2], 255]);
+            } else if d <= radius + outline {
+                rgba[i..i + 4].copy_from_slice(&[20, 20, 20, 255]);
+            }
+        }
+    }
+    Icon::from_rgba(rgba, w, h).expect("to create icon")
+}
+
+/// The tray icon and the menu items that get updated. Tray items aren't `Send`, so this lives on
+/// the GUI thread and is refreshed from `App::update`.
+pub struct Tray {
+    icon: TrayIcon,
+    status_mumble: MenuItem,
+    status_tm: MenuItem,
+    status_context: MenuItem,
+    proximity: CheckMenuItem,
+    shown: Option<TrayStatus>,
+    last_refresh: Instant,
+}
+
+impl Tray {
+    pub fn new(state: &BridgeState) -> Tray {
+        let status_mumble = MenuItem::new("Mumble: ...", false, None);
+        let status_tm = MenuItem::new("Trackmania: ...", false, None);
+        let status_context = MenuItem::new("Context: ...", false, None);
+        let proximity =
+            CheckMenuItem::with_id(MID_PROXIMITY, "Proximity chat", true, state.proximity_enabled(), None);
+        let menu = Menu::with_items(&[
+            &status_mumble,
+            &status_tm,
+            &status_context,
+            &PredefinedMenuItem::separator(),
+            &MenuItem::with_id(MID_SHOW, "Show", true, None),
+            &MenuItem::with_id(MID_HIDE, "Hide", true, None),
+            &MenuItem::with_id(MID_RECONNECT_MUMBLE, "Reconnect Mumble", true, None),
+            &proximity,
+            &PredefinedMenuItem::separator(),
+            &MenuItem::with_id(MID_SOURCE_PLUGIN, "Use Plugin", true, None),
+            &MenuItem::with_id(MID_SOURCE_TELEMETRY, "Use TM Telemetry", true, None),
+            &MenuItem::with_id(MID_SOURCE_HYBRID, "Use Hybrid (Plugin + Telemetry)", true, None),
+            &PredefinedMenuItem::separator(),
+            &MenuItem::with_id(MID_EXIT, "Exit", true, None),
+        ])
+        .expect("to create menu");
+
+        let tray_icon = TrayIconBuilder::new()
+            .with_icon(status_icon(LinkState::Disconnected))
+            .with_tooltip("TM to Mumble Link")
+            .with_menu(Box::new(menu))
+            // left click toggles the window instead
+            .with_menu_on_left_click(false)
+            .build()
+            .expect("to build tray icon");
+
+        Tray {
+            icon: tray_icon,
+            status_mumble,
+            status_tm,
+            status_context,
+            proximity,
+            shown: None,
+            last_refresh: Instant::now(),
+        }
+    }
+
+    /// Update the icon, tooltip and status lines if they changed. Cheap to call every frame.
+    pub fn refresh(&mut self, state: &BridgeState, status: impl FnOnce() -> TrayStatus) {
+        if self.shown.is_some() && self.last_refresh.elapsed() < REFRESH_INTERVAL {
+            return;
+        }
+        self.last_refresh = Instant::now();
+        self.proximity.set_checked(state.proximity_enabled());
+        let status = status();
+        let old = self.shown.as_ref();
+        if old.map(|o| o.link) != Some(status.link) {
+            if let Err(e) = self.icon.set_icon(Some(status_icon(status.link))) {
+                log::warn!("Failed to set tray icon: {}", e);
+            }
+        }
+        if old.map(|o| o.tooltip()) != Some(status.tooltip()) {
+            let _ = self.icon.set_tooltip(Some(status.tooltip()));
+        }
+        if old.map(|o| o.mumble_linked) != Some(status.mumble_linked) {
+            self.status_mumble.set_text(if status.mumble_linked {
+                "Mumble: linked"
+            } else {
+                "Mumble: not linked"
+            });
+        }
+        if old.map(|o| &o.tm) != Some(&status.tm) {
+            self.status_tm.set_text(format!("Trackmania: {}", status.tm));
+        }
+        if old.map(|o| &o.context) != Some(&status.context) {
+            let context = match status.context.as_str() {
+                "" => "none".to_string(),
+                c => truncate(c, MAX_CONTEXT_LEN),
+            };
+            self.status_context.set_text(format!("Context: {}", context));
+        }
+        self.shown = Some(status);
+    }
+}
+
+/// Handle menu and icon clicks. The handlers run outside the GUI thread, so they only talk to
+/// the window through the egui context and to the server through its channel.
+pub fn set_event_handlers(
+    ctx: Context,
+    to_server: Sender<FromGuiToServer>,
+    state: SharedState,
+    exit: ShutdownTrigger,
+) {
+    let menu_ctx = ctx.clone();
+    let menu_state = state.clone();
+    MenuEvent::set_event_handler(Some(move |event: MenuEvent| {
+        let send = |msg| {
+            let _ = to_server.send(msg);
+        };
+        match event.id.0.as_str() {
+            MID_SHOW => set_window_visible(&menu_ctx, &menu_state, true),
+            MID_HIDE => set_window_visible(&menu_ctx, &menu_state, false),
+            MID_RECONNECT_MUMBLE => send(FromGuiToServer::ReconnectMumble()),
+            // the check mark has already flipped; follow our own state so the two can't drift
+            MID_PROXIMITY => menu_state.set_proximity_enabled(!menu_state.proximity_enabled()),
+            MID_SOURCE_PLUGIN => send(FromGuiToServer::UseSource(SourceKind::Plugin)),
+            MID_SOURCE_TELEMETRY => send(FromGuiToServer::UseSource(SourceKind::Telemetry)),
+            MID_SOURCE_HYBRID => send(FromGuiToServer::UseSource(SourceKind::Hybrid)),
+            MID_EXIT => exit.trigger("tray menu"),
+            id => {
+                eprintln!("Unknown menu id: {}", id);
+            }
+        }
+        menu_ctx.request_repaint();
+    }));
+
+    // https://docs.rs/tray-icon/0.12.0/tray_icon/struct.TrayIconEvent.html#method.set_event_handler
+    TrayIconEvent::set_event_handler(Some(move |event: TrayIconEvent| {
+        if let TrayIconEvent::Click {
+            button: MouseButton::Left,
+            button_state: MouseButtonState::Up,
+            ..
+        } = event
+        {
+            set_window_visible(&ctx, &state, !state.is_window_visible());
+        }
+    }));
+}
+
+/// The GUI only refreshes the tray while it's updating, which it stops doing once nothing asks
+/// for a repaint, so keep asking while the window is hidden.
+pub fn spawn_refresh_timer(ctx: Context, state: SharedState) {
+    thread::spawn(move || {
+        while !state.is_shutting_down() {
+            if !state.is_window_visible() {
+                ctx.request_repaint();
+            }
+            thread::sleep(REFRESH_INTERVAL);
+        }
+    });
+}
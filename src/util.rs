@@ -1,4 +1,6 @@
+use windows::core::HSTRING;
 use windows::Win32::UI::Input::KeyboardAndMouse::{GetAsyncKeyState, VK_MENU};
+use windows::Win32::UI::WindowsAndMessaging::{MessageBoxW, MB_ICONINFORMATION, MB_OK};
 
 
 pub fn is_alt_held() -> bool {
@@ -8,3 +10,10 @@ pub fn is_alt_held() -> bool {
         (GetAsyncKeyState(VK_MENU.0 as i32) as u16 & 0x8000u16) != 0
     }
 }
+
+/// A blocking message box, for when there's no window (or console) to tell the user in.
+pub fn show_message(title: &str, text: &str) {
+    unsafe {
+        MessageBoxW(None, &HSTRING::from(text), &HSTRING::from(title), MB_OK | MB_ICONINFORMATION);
+    }
+}